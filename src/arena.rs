@@ -0,0 +1,283 @@
+use chess::Color;
+use openings::Opening;
+use crate::pgn;
+use crate::play::{self, Arbiter, Game, GameMetadata, GameResult, Seed};
+use crate::play::registry::PlayerSpec;
+use std::fmt;
+use std::io::Write;
+
+/* Engine-vs-engine matches, used to compare players */
+
+pub mod tournament;
+pub mod sprt;
+pub mod openings;
+
+pub type ArenaErr = String;
+
+/********** Games **********/
+
+/* The players are built anew for every game, so that nothing leaks from one game to the next */
+fn play_one_game(
+    white:   &PlayerSpec,
+    black:   &PlayerSpec,
+    opening: &Opening,
+    arbiter: &Arbiter,
+    logger:  &mut play::Logger)
+    -> Result<Game, ArenaErr>
+{
+    let mut white_player = white.build()?;
+    let mut black_player = black.build()?;
+    Ok(play::play_game_after(opening.start_pos, &opening.moves, &mut white_player, &mut black_player, arbiter, logger))
+}
+
+/* How a game of a match is set up, besides its players */
+pub struct GameSettings<'a> {
+    pub opening: &'a Opening,
+    pub event:   &'a str,
+    pub round:   u32,
+    pub seed:    Seed,  /* the seeds of the players are derived from it */
+    pub arbiter: &'a Arbiter,
+}
+
+/* Plays a game with `first` as `first_color` after the opening moves,
+ * writes it to the pgn, and tells how it went for `first`.
+ * Unfinished games count as draws.
+ *
+ * Each color gets its own seed derived from the game seed, unless its
+ * spec sets one. The seeds are recorded in the pgn.
+ */
+pub fn play_and_record<W: Write>(
+    first:       &PlayerSpec,
+    second:      &PlayerSpec,
+    first_color: Color,
+    settings:    &GameSettings,
+    pgn_out:     &mut W,
+    logger:      &mut play::Logger)
+    -> Result<(Game, GameResult), ArenaErr>
+{
+    let (white, black) = match first_color {
+        Color::White => (first, second),
+        Color::Black => (second, first),
+    };
+    let white = white.clone().with_seed(play::derive_seed(settings.seed, 0));
+    let black = black.clone().with_seed(play::derive_seed(settings.seed, 1));
+    let mut game = play_one_game(&white, &black, settings.opening, settings.arbiter, logger)?;
+
+    game.metadata = game_metadata(settings.event, Some(settings.round), &white, &black);
+    if !settings.opening.moves.is_empty() {
+        /* the players were not asked for these moves, which a replay must skip */
+        game.metadata.tags.push((String::from(OPENING_PLIES_TAG), settings.opening.moves.len().to_string()));
+    }
+    write_game(pgn_out, &pgn::game_pgn(&game))?;
+
+    let first_result = game.result_for(first_color).unwrap_or(GameResult::Draw);
+    Ok((game, first_result))
+}
+
+/* The parameters of the budget of a player and the suffixes of their
+ * tags: a number of descents or runs replaces the time budget
+ */
+pub const BUDGET_TAGS: [(&str, &str); 3] = [("descents", "Descents"), ("runs", "Runs"), ("time", "Time")];
+
+/* The number of moves of the opening the game started with */
+pub const OPENING_PLIES_TAG: &str = "OpeningPlies";
+
+/* The players are named by their spec. Their configuration comes in
+ * custom tags: the budget and seed of the players that take them.
+ */
+pub fn game_metadata(event: &str, round: Option<u32>, white: &PlayerSpec, black: &PlayerSpec) -> GameMetadata {
+    let mut tags = Vec::new();
+    for (color, spec) in [("White", white), ("Black", black)].iter() {
+        let budget = BUDGET_TAGS.iter()
+                                .find_map(|(key, tag)| spec.param(key).map(|value| (tag, value)));
+        if let Some((tag, value)) = budget {
+            tags.push((format!("{}{}", color, tag), String::from(value)));
+        }
+        if let Some(seed) = spec.seed() {
+            tags.push((format!("{}Seed", color), seed.to_string()));
+        }
+    }
+
+    GameMetadata {
+        event: String::from(event),
+        site:  String::from("?"),
+        date:  pgn::date_today(),
+        round: round.map_or(String::from("-"), |round| round.to_string()),
+        white: white.to_string(),
+        black: black.to_string(),
+        tags,
+    }
+}
+
+/* Handed to the caller after each game, e.g. to show the progress */
+pub struct GameReport<'a> {
+    pub round: u32,
+    pub white: &'a PlayerSpec,
+    pub black: &'a PlayerSpec,
+    pub game:  &'a Game,
+}
+
+/* Games are separated by an empty line in a multi-game pgn */
+fn write_game<W: Write>(pgn_out: &mut W, game_pgn: &str) -> Result<(), ArenaErr> {
+    writeln!(pgn_out, "{}", game_pgn)
+        .and_then(|_| pgn_out.flush())
+        .map_err(|reason| format!("Couldn't write the pgn: {}", reason))
+}
+
+/********** Record **********/
+
+/* Wins, draws and losses of one player against another */
+#[derive(Clone, Copy, Default)]
+pub struct Record {
+    pub wins:   u32,
+    pub draws:  u32,
+    pub losses: u32,
+}
+
+impl Record {
+    pub fn add(&mut self, result: GameResult) {
+        match result {
+            GameResult::Win  => self.wins += 1,
+            GameResult::Draw => self.draws += 1,
+            GameResult::Lose => self.losses += 1,
+        }
+    }
+
+    pub fn n_games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /* A win is worth one point and a draw half a point */
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + 0.5 * self.draws as f64
+    }
+
+    /* Mean and variance of the score of a single game, None without any game */
+    pub fn score_stats(&self) -> Option<(f64, f64)> {
+        score_stats(self.wins as f64, self.draws as f64, self.losses as f64)
+    }
+
+    /* The same games, seen from the opponent */
+    pub fn reversed(&self) -> Record {
+        Record {
+            wins:   self.losses,
+            draws:  self.draws,
+            losses: self.wins,
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)
+    }
+}
+
+/* Takes real numbers of games, to allow for pseudo-counts */
+pub fn score_stats(wins: f64, draws: f64, losses: f64) -> Option<(f64, f64)> {
+    let n_games = wins + draws + losses;
+    if n_games == 0. {
+        return None;
+    }
+
+    let mean = (wins + 0.5 * draws) / n_games;
+    let dev = |game_score: f64| (game_score - mean).powi(2);
+    let variance = (wins * dev(1.) + draws * dev(0.5) + losses * dev(0.)) / n_games;
+    Some((mean, variance))
+}
+
+/********** Elo **********/
+
+/* Elo difference corresponding to an expected score in ]0, 1[ */
+pub fn elo_from_score(score: f64) -> f64 {
+    400. * (score / (1. - score)).log10()
+}
+
+pub struct EloEstimate {
+    pub diff:   f64,
+    pub margin: f64,  /* 95% confidence */
+}
+
+const Z_95: f64 = 1.96;
+
+/* None when the score is 0% or 100%: the Elo difference is infinite */
+pub fn estimate_elo(record: &Record) -> Option<EloEstimate> {
+    let (score, variance) = record.score_stats()?;
+    if score <= 0. || score >= 1. {
+        return None;
+    }
+    let std_error = (variance / record.n_games() as f64).sqrt();
+
+    /* Clamp the bounds to keep them finite */
+    let epsilon = 1e-6;
+    let score_low  = (score - Z_95 * std_error).max(epsilon);
+    let score_high = (score + Z_95 * std_error).min(1. - epsilon);
+
+    Some(EloEstimate {
+        diff:   elo_from_score(score),
+        margin: (elo_from_score(score_high) - elo_from_score(score_low)) / 2.,
+    })
+}
+
+impl fmt::Display for EloEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:+.1} +/- {:.1}", self.diff, self.margin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(wins: u32, draws: u32, losses: u32) -> Option<EloEstimate> {
+        estimate_elo(&Record { wins, draws, losses })
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn even_records_are_no_elo_difference() {
+        let elo = estimate(10, 10, 10).unwrap();
+        assert_close(elo.diff, 0.);
+        assert_close(elo.margin, 104.55990273100073);
+    }
+
+    #[test]
+    fn reversed_records_are_opposite_elo_differences() {
+        let stronger = estimate(30, 10, 10).unwrap();
+        let weaker = estimate(10, 10, 30).unwrap();
+        assert_close(stronger.diff, 147.19071411783773);
+        assert_close(weaker.diff, -stronger.diff);
+        assert_close(stronger.margin, 95.1408392440413);
+        assert_close(weaker.margin, stronger.margin);
+    }
+
+    #[test]
+    fn a_score_of_76_percent_is_about_200_elo() {
+        let elo = estimate(76, 0, 24).unwrap();
+        assert_close(elo.diff, 200.24094022767414);
+        assert_close(elo.margin, 82.45522993884227);
+        assert_eq!(elo.to_string(), "+200.2 +/- 82.5");
+    }
+
+    #[test]
+    fn the_margin_shrinks_with_more_games() {
+        let few = estimate(6, 2, 2).unwrap();
+        let many = estimate(60, 20, 20).unwrap();
+        assert_close(few.diff, many.diff);
+        assert!(many.margin < few.margin);
+    }
+
+    #[test]
+    fn perfect_scores_have_no_finite_estimate() {
+        assert!(estimate(5, 0, 0).is_none());
+        assert!(estimate(0, 0, 5).is_none());
+        assert!(estimate(0, 0, 0).is_none());
+        /* Draws only: no difference, and no variance either */
+        let elo = estimate(0, 5, 0).unwrap();
+        assert_close(elo.diff, 0.);
+        assert_close(elo.margin, 0.);
+    }
+}
@@ -0,0 +1,158 @@
+use chess::{Board, ChessMove};
+use super::ArenaErr;
+use crate::epd;
+use crate::pgn;
+use std::path::Path;
+
+/* Opening suites, to start the games of a match from varied but sound
+ * positions. A suite is a text file with one opening per line, either:
+ *
+ *   - a FEN or EPD position (EPD operations are ignored), e.g.
+ *       rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - bm Nf3;
+ *
+ *   - the beginning of a game in PGN, e.g.
+ *       1. e4 e5 2. Nf3 Nc6
+ *     Comments, NAGs and variations are allowed, and a comment or a
+ *     variation left open goes on with the next lines. After tag pairs,
+ *     e.g. a FEN tag for the position the moves start from, the moves go
+ *     on up to the next empty line, as in a pgn file.
+ *
+ * Empty lines and lines starting with '#' are ignored.
+ */
+
+#[derive(Clone, Default)]
+pub struct Opening {
+    pub start_pos: Board,
+    pub moves:     Vec<ChessMove>,  /* played from start_pos */
+}
+
+/* Each opening is played twice in a row, with colors reversed */
+pub fn opening_for_game(openings: &[Opening], game_idx: u32) -> Opening {
+    if openings.is_empty() {
+        return Opening::default();
+    }
+    openings[(game_idx as usize / 2) % openings.len()].clone()
+}
+
+pub fn load_openings(path: &Path) -> Result<Vec<Opening>, ArenaErr> {
+    let content = std::fs::read_to_string(path)
+                     .map_err(|reason| format!("Couldn't read {}: {}", path.display(), reason))?;
+    read_openings(&content)
+        .map_err(|reason| format!("{}: {}", path.display(), reason))
+}
+
+/* The text of a PGN opening, which may span lines */
+#[derive(Default)]
+struct PgnText {
+    text:       String,
+    first_line: usize,  /* counted from 1 */
+    has_tags:   bool,
+    has_moves:  bool,
+}
+
+impl PgnText {
+    fn push_line(&mut self, line_idx: usize, line: &str) {
+        if self.text.is_empty() {
+            self.first_line = line_idx + 1;
+        }
+        self.text.push_str(line);
+        self.text.push('\n');
+    }
+
+    /* Reads the opening, and starts over for the next one */
+    fn take_opening(&mut self) -> Result<Opening, ArenaErr> {
+        let pgn_text = std::mem::take(self);
+        let annotated_game = pgn::read_pgn(&pgn_text.text)
+                                 .map_err(|err| err.shifted(pgn_text.first_line).to_string())?;
+        Ok(Opening {
+            start_pos: annotated_game.game.init_board,
+            moves:     annotated_game.game.moves,
+        })
+    }
+}
+
+pub fn read_openings(input: &str) -> Result<Vec<Opening>, ArenaErr> {
+    let mut openings = Vec::new();
+    let mut pgn_text = PgnText::default();
+
+    for (line_idx, line) in input.lines().enumerate() {
+        let trimmed = line.trim();
+
+        /* After tags, the moves end at an empty line or at the next tags */
+        let goes_on = pgn_text.has_moves
+                      && (is_unfinished(&pgn_text.text)
+                          || (pgn_text.has_tags && !trimmed.is_empty() && !trimmed.starts_with('[')));
+        if goes_on {
+            pgn_text.push_line(line_idx, line);
+            continue;
+        }
+        if pgn_text.has_moves {
+            openings.push(pgn_text.take_opening()?);
+        }
+
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            if !pgn_text.text.is_empty() {
+                /* between the tags and the moves, kept for the line numbers */
+                pgn_text.push_line(line_idx, "");
+            }
+            continue;
+        }
+        else if trimmed.starts_with('[') {
+            pgn_text.push_line(line_idx, line);
+            pgn_text.has_tags = true;
+        }
+        else if is_position(trimmed) {
+            let start_pos = epd::parse_position(trimmed)
+                                .map_err(|reason| format!("line {}: {}", line_idx + 1, reason))?;
+            openings.push(Opening {
+                start_pos,
+                moves: Vec::new(),
+            });
+        }
+        else {
+            pgn_text.push_line(line_idx, line);
+            pgn_text.has_moves = true;
+            if !pgn_text.has_tags && !is_unfinished(&pgn_text.text) {
+                openings.push(pgn_text.take_opening()?);
+            }
+        }
+    }
+
+    if pgn_text.has_moves {
+        openings.push(pgn_text.take_opening()?);
+    }
+
+    if openings.is_empty() {
+        return Err(String::from("No opening found"));
+    }
+    Ok(openings)
+}
+
+/* The piece placement is the only part of a FEN with slashes */
+fn is_position(line: &str) -> bool {
+    line.split_whitespace()
+        .next()
+        .is_some_and(|first| first.contains('/'))
+}
+
+/* Whether the move text leaves a {comment} or a (variation) open */
+fn is_unfinished(text: &str) -> bool {
+    let mut in_comment = false;
+    let mut depth = 0;
+    for line in text.lines() {
+        if line.trim_start().starts_with('[') && !in_comment {
+            continue;
+        }
+        for c in line.chars() {
+            match c {
+                '{' if !in_comment => in_comment = true,
+                '}' if in_comment  => in_comment = false,
+                ';' if !in_comment => break,  /* the rest of the line is a comment */
+                '(' if !in_comment => depth += 1,
+                ')' if !in_comment => depth -= 1,
+                _                  => { }
+            }
+        }
+    }
+    in_comment || depth > 0
+}
@@ -0,0 +1,240 @@
+use chess::Color;
+use super::{ArenaErr, GameReport, GameSettings, Record};
+use super::openings::{self, Opening};
+use crate::play::{self, Arbiter, Seed};
+use crate::play::registry::PlayerSpec;
+use std::fmt;
+use std::io::Write;
+
+/* Sequential Probability Ratio Test: play games between a candidate and a
+ * baseline until it is clear enough whether the candidate is elo0 or elo1
+ * points stronger.
+ *
+ * The log-likelihood ratio uses the usual normal approximation of the
+ * average score, see e.g.
+ *   https://www.chessprogramming.org/Sequential_Probability_Ratio_Test
+ */
+
+#[derive(Clone, Copy)]
+pub struct SprtParams {
+    pub elo0:  f64,  /* H0: the candidate is elo0 stronger */
+    pub elo1:  f64,  /* H1: the candidate is elo1 stronger */
+    pub alpha: f64,  /* probability of accepting H1 when H0 is true */
+    pub beta:  f64,  /* probability of accepting H0 when H1 is true */
+}
+
+impl Default for SprtParams {
+    fn default() -> Self {
+        SprtParams {
+            elo0:  0.,
+            elo1:  10.,
+            alpha: 0.05,
+            beta:  0.05,
+        }
+    }
+}
+
+impl SprtParams {
+    /* (lower, upper): H0 is accepted below, H1 above */
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1. - self.alpha)).ln(),
+         ((1. - self.beta) / self.alpha).ln())
+    }
+
+    pub fn check(&self) -> Result<(), ArenaErr> {
+        let is_probability = |p: f64| p > 0. && p < 1.;
+        if !is_probability(self.alpha) || !is_probability(self.beta) {
+            return Err(String::from("SPRT alpha and beta must be between 0 and 1"));
+        }
+        if self.elo0 >= self.elo1 {
+            return Err(String::from("SPRT elo0 must be lower than elo1"));
+        }
+        Ok(())
+    }
+}
+
+/* Expected score of a player that is `elo` points stronger than its opponent */
+fn score_from_elo(elo: f64) -> f64 {
+    1. / (1. + 10f64.powf(-elo / 400.))
+}
+
+/* Half a game is added to each outcome: without it, a candidate that
+ * wins all its games would have a null variance and the test would
+ * never end.
+ */
+const PSEUDO_COUNT: f64 = 0.5;
+
+pub fn llr(record: &Record, params: &SprtParams) -> f64 {
+    let (score, variance) = super::score_stats(record.wins as f64 + PSEUDO_COUNT,
+                                               record.draws as f64 + PSEUDO_COUNT,
+                                               record.losses as f64 + PSEUDO_COUNT)
+                                .unwrap();
+    let n_games = record.n_games() as f64 + 3. * PSEUDO_COUNT;
+
+    let score0 = score_from_elo(params.elo0);
+    let score1 = score_from_elo(params.elo1);
+    n_games * (score1 - score0) * (2. * score - score0 - score1) / (2. * variance)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Undecided,
+    AcceptH0,
+    AcceptH1,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Undecided => write!(f, "undecided"),
+            Verdict::AcceptH0  => write!(f, "H0 accepted"),
+            Verdict::AcceptH1  => write!(f, "H1 accepted"),
+        }
+    }
+}
+
+/* State of the test after a game */
+#[derive(Clone, Copy)]
+pub struct SprtState {
+    pub record:  Record,  /* of the candidate */
+    pub llr:     f64,
+    pub verdict: Verdict,
+}
+
+pub struct Sprt {
+    pub event:        String,
+    pub candidate:    PlayerSpec,
+    pub baseline:     PlayerSpec,
+    pub params:       SprtParams,
+    pub max_games:    Option<u32>,    /* stop undecided after that many games */
+    pub openings:     Vec<Opening>,   /* none to start from the initial position */
+    pub arbiter:      Arbiter,
+    pub seed:         Seed,           /* the seed of each game is derived from it */
+}
+
+/* Plays games, alternating colors, until a bound is crossed.
+ * The games are written to pgn_out as they are finished.
+ */
+pub fn run_sprt<W, F>(
+    sprt:        &Sprt,
+    pgn_out:     &mut W,
+    logger:      &mut play::Logger,
+    mut on_game: F)
+    -> Result<SprtState, ArenaErr>
+    where
+        W: Write,
+        F: FnMut(&GameReport, &SprtState)
+{
+    sprt.params.check()?;
+    sprt.candidate.build()?;
+    sprt.baseline.build()?;
+
+    let (lower, upper) = sprt.params.bounds();
+    let mut state = SprtState {
+        record:  Record::default(),
+        llr:     0.,
+        verdict: Verdict::Undecided,
+    };
+    let mut round = 0;
+
+    while state.verdict == Verdict::Undecided && sprt.max_games.is_none_or(|max| round < max) {
+        let candidate_color = if round % 2 == 0 { Color::White } else { Color::Black };
+        let opening = openings::opening_for_game(&sprt.openings, round);
+        round += 1;
+
+        let settings = GameSettings {
+            opening: &opening,
+            event:   &sprt.event,
+            round,
+            seed:    play::derive_seed(sprt.seed, round as u64),
+            arbiter: &sprt.arbiter,
+        };
+        let (game, candidate_result) = super::play_and_record(&sprt.candidate,
+                                                              &sprt.baseline,
+                                                              candidate_color,
+                                                              &settings,
+                                                              pgn_out,
+                                                              logger)?;
+
+        state.record.add(candidate_result);
+        state.llr = llr(&state.record, &sprt.params);
+        state.verdict = if state.llr <= lower { Verdict::AcceptH0 }
+                        else if state.llr >= upper { Verdict::AcceptH1 }
+                        else { Verdict::Undecided };
+
+        let (white, black) = match candidate_color {
+            Color::White => (&sprt.candidate, &sprt.baseline),
+            Color::Black => (&sprt.baseline, &sprt.candidate),
+        };
+        on_game(&GameReport { round, white, black, game: &game }, &state);
+    }
+
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(wins: u32, draws: u32, losses: u32) -> Record {
+        Record { wins, draws, losses }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn bounds_follow_alpha_and_beta() {
+        let (lower, upper) = SprtParams::default().bounds();
+        assert_close(lower, (0.05f64 / 0.95).ln());
+        assert_close(upper, (0.95f64 / 0.05).ln());
+    }
+
+    #[test]
+    fn llr_crosses_the_upper_bound_for_a_stronger_candidate() {
+        let params = SprtParams::default();
+        let (lower, upper) = params.bounds();
+
+        let llr_value = llr(&record(300, 400, 200), &params);
+        assert_close(llr_value, 4.608146762490845);
+        assert!(llr_value >= upper);
+
+        let llr_value = llr(&record(60, 100, 40), &params);
+        assert_close(llr_value, 1.0013857495877312);
+        assert!(llr_value > lower && llr_value < upper);
+    }
+
+    #[test]
+    fn llr_crosses_the_lower_bound_for_a_weaker_candidate() {
+        let params = SprtParams::default();
+        let (lower, upper) = params.bounds();
+
+        let llr_value = llr(&record(200, 400, 300), &params);
+        assert_close(llr_value, -5.981648080611939);
+        assert!(llr_value <= lower);
+
+        let llr_value = llr(&record(40, 100, 60), &params);
+        assert_close(llr_value, -1.340902492615297);
+        assert!(llr_value > lower && llr_value < upper);
+    }
+
+    #[test]
+    fn pseudo_counts_keep_one_sided_records_finite() {
+        let params = SprtParams::default();
+        let (lower, upper) = params.bounds();
+
+        /* Without games, only the pseudo-counts are left: barely below 0 as elo0 < elo1 */
+        assert_close(llr(&record(0, 0, 0), &params), -0.0009314598070186383);
+
+        /* Without pseudo-counts, the variance of these would be null */
+        assert_close(llr(&record(10, 0, 0), &params), 1.4122447938599643);
+        assert_close(llr(&record(0, 0, 10), &params), -1.4597629132394012);
+
+        /* But enough of them are decisive */
+        assert!(llr(&record(14, 0, 0), &params) < upper);
+        assert!(llr(&record(15, 0, 0), &params) >= upper);
+        assert!(llr(&record(0, 0, 14), &params) > lower);
+        assert!(llr(&record(0, 0, 15), &params) <= lower);
+    }
+}
@@ -0,0 +1,159 @@
+use chess::Color;
+use super::{ArenaErr, GameReport, GameSettings, Record};
+use super::openings::{self, Opening};
+use crate::play::{self, Arbiter, GameResult, Seed};
+use crate::play::registry::PlayerSpec;
+use std::io::Write;
+
+/* Round-robin tournament: every entrant plays the same number of games
+ * against every other one, alternating colors.
+ */
+
+pub struct Tournament {
+    pub event:             String,
+    pub entrants:          Vec<PlayerSpec>,
+    pub games_per_pairing: u32,
+    pub openings:          Vec<Opening>,  /* none to start from the initial position */
+    pub arbiter:           Arbiter,
+    pub seed:              Seed,          /* the seed of each game is derived from it */
+}
+
+pub struct Standings {
+    pub names: Vec<String>,
+    records:   Vec<Vec<Record>>,  /* records[i][j]: games of entrant i against entrant j */
+}
+
+impl Standings {
+    fn new(names: Vec<String>) -> Self {
+        let n_entrants = names.len();
+        Standings {
+            names,
+            records: vec![vec![Record::default(); n_entrants]; n_entrants],
+        }
+    }
+
+    fn add(&mut self, first: usize, second: usize, first_result: GameResult) {
+        self.records[first][second].add(first_result);
+        self.records[second][first] = self.records[first][second].reversed();
+    }
+
+    pub fn record(&self, entrant: usize, opponent: usize) -> Record {
+        self.records[entrant][opponent]
+    }
+
+    pub fn total(&self, entrant: usize) -> Record {
+        self.records[entrant].iter()
+                             .fold(Record::default(), |total, record| Record {
+                                 wins:   total.wins + record.wins,
+                                 draws:  total.draws + record.draws,
+                                 losses: total.losses + record.losses,
+                             })
+    }
+
+    /* Pairs (i, j) with i < j, in the order they were played */
+    pub fn pairings(&self) -> impl Iterator<Item = (usize, usize)> {
+        pairings(self.names.len())
+    }
+
+    /* Entrants from first to last */
+    pub fn ranking(&self) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0..self.names.len()).collect();
+        ranking.sort_by(|a, b| self.total(*b).points()
+                                   .partial_cmp(&self.total(*a).points())
+                                   .unwrap());
+        ranking
+    }
+}
+
+fn pairings(n_entrants: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..n_entrants).flat_map(move |first| ((first + 1)..n_entrants).map(move |second| (first, second)))
+}
+
+/* Plays all the games, and writes them to pgn_out as they are finished */
+pub fn run_tournament<W, F>(
+    tournament: &Tournament,
+    pgn_out:    &mut W,
+    logger:     &mut play::Logger,
+    mut on_game: F)
+    -> Result<Standings, ArenaErr>
+    where
+        W: Write,
+        F: FnMut(&GameReport)
+{
+    if tournament.entrants.len() < 2 {
+        return Err(String::from("A tournament needs at least two entrants"));
+    }
+    /* Fail early rather than in the middle of the tournament */
+    for spec in tournament.entrants.iter() {
+        spec.build()?;
+    }
+
+    let names = tournament.entrants.iter().map(|spec| spec.to_string()).collect();
+    let mut standings = Standings::new(names);
+    let mut round = 0;
+
+    for (first, second) in pairings(tournament.entrants.len()) {
+        for game_idx in 0..tournament.games_per_pairing {
+            round += 1;
+            let first_color = if game_idx % 2 == 0 { Color::White } else { Color::Black };
+            let settings = GameSettings {
+                opening: &openings::opening_for_game(&tournament.openings, game_idx),
+                event:   &tournament.event,
+                round,
+                seed:    play::derive_seed(tournament.seed, round as u64),
+                arbiter: &tournament.arbiter,
+            };
+            let (game, first_result) = super::play_and_record(&tournament.entrants[first],
+                                                              &tournament.entrants[second],
+                                                              first_color,
+                                                              &settings,
+                                                              pgn_out,
+                                                              logger)?;
+            standings.add(first, second, first_result);
+
+            let (white, black) = match first_color {
+                Color::White => (first, second),
+                Color::Black => (second, first),
+            };
+            on_game(&GameReport {
+                round,
+                white: &tournament.entrants[white],
+                black: &tournament.entrants[black],
+                game:  &game,
+            });
+        }
+    }
+
+    Ok(standings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_pair_of_entrants_meets_once() {
+        let pairs: Vec<_> = pairings(4).collect();
+        assert_eq!(pairs, [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn records_are_kept_from_both_sides_and_ranked_by_points() {
+        let mut standings = Standings::new(vec![String::from("a"), String::from("b"), String::from("c")]);
+        standings.add(0, 1, GameResult::Lose);
+        standings.add(0, 1, GameResult::Draw);
+        standings.add(0, 2, GameResult::Win);
+        standings.add(1, 2, GameResult::Win);
+        standings.add(1, 2, GameResult::Win);
+
+        assert_eq!(standings.record(1, 0).to_string(), "+1 =1 -0");
+        assert_eq!(standings.record(0, 1).to_string(), "+0 =1 -1");
+        assert_eq!(standings.total(1).to_string(), "+3 =1 -0");
+        assert_eq!(standings.total(2).to_string(), "+0 =0 -3");
+        assert_eq!(standings.ranking(), [1, 0, 2]);
+
+        /* The Elo of an entrant comes from its total */
+        let elo = crate::arena::estimate_elo(&standings.total(0)).unwrap();
+        assert!(elo.diff.abs() < 1e-6);
+    }
+}
@@ -0,0 +1,122 @@
+use chess::{Board, ChessMove, MoveGen};
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+/* A scripted stand-in for a UCI engine, to test the external engine player.
+ * It always plays the first legal move.
+ *
+ *   mock_uci_engine [--delay <ms>] [--crash-after <n>] [--hang] [--illegal]
+ *
+ *   --delay <ms>        wait before answering 'go'
+ *   --crash-after <n>   exit without answering the n+1-th 'go'
+ *   --hang              never answer 'go', nor 'stop'
+ *   --illegal           answer 'go' with an illegal move
+ */
+
+struct Behavior {
+    delay:       Duration,
+    crash_after: Option<u32>,
+    hang:        bool,
+    illegal:     bool,
+}
+
+fn parse_args() -> Behavior {
+    let mut behavior = Behavior {
+        delay:       Duration::from_millis(0),
+        crash_after: None,
+        hang:        false,
+        illegal:     false,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut number = || args.next()
+                                .and_then(|n| n.parse::<u64>().ok())
+                                .unwrap_or_else(|| panic!("{} expects a number", arg));
+        match arg.as_str() {
+            "--delay"       => behavior.delay = Duration::from_millis(number()),
+            "--crash-after" => behavior.crash_after = Some(number() as u32),
+            "--hang"        => behavior.hang = true,
+            "--illegal"     => behavior.illegal = true,
+            _               => panic!("Unknown argument {}", arg),
+        }
+    }
+
+    behavior
+}
+
+/* position (startpos | fen <fen>) [moves <move>...] */
+fn parse_position(args: &[&str]) -> Option<Board> {
+    let moves_idx = args.iter().position(|arg| *arg == "moves").unwrap_or(args.len());
+    let mut board = match args.first() {
+        Some(&"startpos") => Board::default(),
+        Some(&"fen")      => Board::from_str(&args[1..moves_idx].join(" ")).ok()?,
+        _                 => return None,
+    };
+
+    for mv_str in args.iter().skip(moves_idx + 1) {
+        let mv = MoveGen::new_legal(&board).find(|mv| mv.to_string() == *mv_str)?;
+        board = board.make_move_new(mv);
+    }
+    Some(board)
+}
+
+fn main() {
+    let behavior = parse_args();
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut board = Board::default();
+    let mut n_goes = 0;
+
+    for line in stdin.lock().lines() {
+        let line = line.expect("Couldn't read stdin");
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.first() {
+            Some(&"uci") => {
+                writeln!(stdout, "id name mock").unwrap();
+                writeln!(stdout, "id author nobody").unwrap();
+                writeln!(stdout, "uciok").unwrap();
+            }
+
+            Some(&"isready") => writeln!(stdout, "readyok").unwrap(),
+
+            Some(&"position") =>
+                if let Some(new_board) = parse_position(&tokens[1..]) {
+                    board = new_board;
+                },
+
+            Some(&"go") => {
+                if behavior.crash_after == Some(n_goes) {
+                    std::process::exit(1);
+                }
+                n_goes += 1;
+                if behavior.hang {
+                    continue;
+                }
+
+                std::thread::sleep(behavior.delay);
+                let best_move = if behavior.illegal {
+                    Some(ChessMove::new(chess::Square::A1, chess::Square::H8, None))
+                }
+                else {
+                    MoveGen::new_legal(&board).next()
+                };
+                match best_move {
+                    Some(mv) => {
+                        writeln!(stdout, "info depth 1 nodes 1 score cp 0 pv {}", mv).unwrap();
+                        writeln!(stdout, "bestmove {}", mv).unwrap();
+                    }
+                    None => writeln!(stdout, "bestmove 0000").unwrap(),
+                }
+            }
+
+            Some(&"quit") => return,
+
+            _ => { /* unknown commands are ignored, as UCI requires */ }
+        }
+
+        stdout.flush().unwrap();
+    }
+}
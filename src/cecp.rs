@@ -0,0 +1,254 @@
+use chess::{Board, BoardStatus, Color};
+use crate::play::{self, ChessPlayer, Decision, Game, Termination};
+use crate::play::clock::ClockInfo;
+use crate::uci;
+use std::io::{self, BufRead, Write};
+use std::str::{FromStr, SplitWhitespace};
+use std::time::Duration;
+
+/* A driver for the Chess Engine Communication Protocol (a.k.a. xboard).
+ * The protocol is described in https://www.gnu.org/software/xboard/engine-intf.html
+ *
+ * Moves are exchanged in coordinate notation, in both directions.
+ * As with UCI, the search is run synchronously.
+ */
+
+const ENGINE_NAME: &str = "hakarl";
+
+/* How the engine is allowed to spend its time */
+struct TimeControl {
+    fixed_budget:      Option<Duration>,  /* 'st' */
+    moves_per_session: Option<u32>,       /* 'level', None for the whole game */
+    increment:         Duration,
+    time_left:         Option<Duration>,  /* last 'time' received */
+    opponent_time:     Option<Duration>,  /* last 'otim' received */
+}
+
+struct CecpState {
+    game:       Game,
+    force_mode: bool,
+    time_ctrl:  TimeControl,
+}
+
+impl CecpState {
+    fn new() -> Self {
+        CecpState {
+            game:       Game::new(),
+            force_mode: false,
+            time_ctrl:  TimeControl {
+                fixed_budget:      None,
+                moves_per_session: None,
+                increment:         Duration::from_secs(0),
+                time_left:         None,
+                opponent_time:     None,
+            }
+        }
+    }
+
+    /* Without any time control, the player keeps its own budget */
+    fn set_time_control<P: ChessPlayer>(&self, player: &mut P) {
+        let time_ctrl = &self.time_ctrl;
+        if let Some(budget) = time_ctrl.fixed_budget {
+            return player.set_time_budget(budget);
+        }
+
+        let moves_to_go = time_ctrl.moves_per_session.map(|mps| {
+            let moves_played = (self.game.moves.len() / 2) as u32;
+            mps - (moves_played % mps)
+        });
+
+        if let Some(time_left) = time_ctrl.time_left {
+            player.set_clock(&ClockInfo {
+                time_left,
+                opponent_time_left: time_ctrl.opponent_time.unwrap_or(time_left),
+                increment:          time_ctrl.increment,
+                moves_to_go,
+            });
+        }
+    }
+}
+
+pub fn run_cecp<P: ChessPlayer>(player: &mut P, logger: &mut play::Logger) -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    run_cecp_on(player, stdin.lock(), stdout.lock(), logger)
+}
+
+pub fn run_cecp_on<P, R, W>(
+    player: &mut P,
+    input:  R,
+    output: W,
+    logger: &mut play::Logger)
+    -> io::Result<()>
+    where
+        P: ChessPlayer,
+        R: BufRead,
+        W: Write
+{
+    let mut output = output;
+    let mut state = CecpState::new();
+
+    for line in input.lines() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("xboard") => { /* nothing to do, we only speak CECP here */ }
+
+            Some("protover") => {
+                writeln!(output, "feature myname=\"{}\" usermove=1 setboard=1 ping=1 \
+                                  san=0 sigint=0 sigterm=0 colors=0 done=1",
+                         ENGINE_NAME)?;
+            }
+
+            Some("new") => {
+                state.game = Game::new();
+                state.force_mode = false;
+                state.time_ctrl.fixed_budget = None;
+            }
+
+            Some("force") => state.force_mode = true,
+
+            Some("setboard") => {
+                let fen = tokens.collect::<Vec<_>>().join(" ");
+                match Board::from_str(&fen) {
+                    Ok(board) => state.game = Game::starting_from(board),
+                    Err(err)  => writeln!(output, "tellusererror Illegal position: {}", err)?,
+                }
+            }
+
+            Some("usermove") => {
+                let mv_str = tokens.next().unwrap_or("");
+                match uci::parse_coord_move(&state.game.final_board, mv_str) {
+                    Some(mv) => {
+                        state.game.play_move(mv);
+                        if !state.force_mode {
+                            engine_move(player, &mut state, &mut output, logger)?;
+                        }
+                    }
+                    None => writeln!(output, "Illegal move: {}", mv_str)?,
+                }
+            }
+
+            Some("go") => {
+                state.force_mode = false;
+                engine_move(player, &mut state, &mut output, logger)?;
+            }
+
+            Some("level") => {
+                match parse_level(tokens) {
+                    Some((mps, base, inc)) => {
+                        let time_ctrl = &mut state.time_ctrl;
+                        time_ctrl.fixed_budget = None;
+                        time_ctrl.moves_per_session = if mps == 0 { None } else { Some(mps) };
+                        time_ctrl.increment = inc;
+                        time_ctrl.time_left = Some(base);
+                    }
+                    None => writeln!(output, "Error (bad arguments): {}", line)?,
+                }
+            }
+
+            Some("st") => {
+                match tokens.next().and_then(|secs| secs.parse::<u64>().ok()) {
+                    Some(secs) => state.time_ctrl.fixed_budget = Some(Duration::from_secs(secs)),
+                    None       => writeln!(output, "Error (bad arguments): {}", line)?,
+                }
+            }
+
+            Some("time") => {
+                /* our own clock, in centiseconds */
+                state.time_ctrl.time_left = parse_centiseconds(tokens.next());
+            }
+
+            Some("otim") => state.time_ctrl.opponent_time = parse_centiseconds(tokens.next()),
+
+            Some("ping") => writeln!(output, "pong {}", tokens.next().unwrap_or(""))?,
+
+            Some("quit") => return Ok(()),
+
+            Some("accepted") | Some("rejected") | Some("random") | Some("post") |
+            Some("nopost") | Some("hard") | Some("easy") | Some("computer") |
+            Some("result") => { /* nothing to do */ }
+
+            Some(other) => writeln!(output, "Error (unknown command): {}", other)?,
+
+            None => { /* empty line */ }
+        }
+
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+fn parse_centiseconds(token: Option<&str>) -> Option<Duration> {
+    token.and_then(|cs| cs.parse::<u64>().ok())
+         .map(|cs| Duration::from_millis(10 * cs))
+}
+
+/* level MPS BASE INC, where BASE is either "min" or "min:sec" */
+fn parse_level(mut tokens: SplitWhitespace) -> Option<(u32, Duration, Duration)> {
+    let mps = tokens.next()?.parse::<u32>().ok()?;
+
+    let base_str = tokens.next()?;
+    let mut base_parts = base_str.split(':');
+    let minutes = base_parts.next()?.parse::<u64>().ok()?;
+    let seconds = match base_parts.next() {
+        Some(secs) => secs.parse::<u64>().ok()?,
+        None       => 0,
+    };
+    let base = Duration::from_secs(minutes.checked_mul(60)?.checked_add(seconds)?);
+
+    /* Negative, NaN, infinite or too large increments are rejected */
+    let inc_secs = tokens.next()?.parse::<f64>().ok()?;
+    let inc = Duration::try_from_secs_f64(inc_secs).ok()?;
+
+    Some((mps, base, inc))
+}
+
+fn engine_move<P: ChessPlayer, W: Write>(
+    player: &mut P,
+    state:  &mut CecpState,
+    output: &mut W,
+    logger: &mut play::Logger)
+    -> io::Result<()>
+{
+    if state.game.is_over() {
+        return print_result(&state.game, output);
+    }
+
+    state.set_time_control(player);
+
+    let mv = match player.decide(&state.game.final_board, &state.game.history, logger) {
+        Decision::Play(mv) => mv,
+        /* The player gives up, e.g. its engine died */
+        _ => {
+            let side = state.game.final_board.side_to_move();
+            state.game.ended_by = Some(Termination::Resignation(side));
+            return writeln!(output, "resign");
+        }
+    };
+    state.game.play_move(mv);
+    writeln!(output, "move {}", mv)?;
+
+    if state.game.is_over() {
+        print_result(&state.game, output)?;
+    }
+    Ok(())
+}
+
+fn print_result<W: Write>(game: &Game, output: &mut W) -> io::Result<()> {
+    match game.final_board.status() {
+        BoardStatus::Checkmate =>
+            match game.winner().unwrap() {
+                Color::White => writeln!(output, "1-0 {{White mates}}"),
+                Color::Black => writeln!(output, "0-1 {{Black mates}}"),
+            },
+        BoardStatus::Stalemate => writeln!(output, "1/2-1/2 {{Stalemate}}"),
+        BoardStatus::Ongoing   =>
+            match game.draw_rule() {
+                Some(rule) => writeln!(output, "1/2-1/2 {{Draw by {}}}", rule),
+                None       => Ok(()),
+            },
+    }
+}
@@ -0,0 +1,287 @@
+use crate::arena::openings::{self, Opening};
+use crate::arena::sprt::SprtParams;
+use crate::logging::LogLevel;
+use crate::play;
+use crate::play::astar::astarparl::ThreadCount;
+use crate::play::registry::{self, PlayerSpec};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/* Command-line interface
+ *
+ *   hakarl <command> [options]
+ *
+ * Every option has a default value, so that 'hakarl play' alone
+ * reproduces what used to be hardcoded in main().
+ */
+
+pub const USAGE: &str = "\
+Usage: hakarl <command> [options]
+
+Commands:
+  play                  play a single game between --white and --black
+  match                 play --games games between --white and --black, alternating colors
+  tournament            round robin between the --entrant players, --games games per pairing
+  sprt                  play --white against --black, alternating colors, until a
+                        sequential probability ratio test tells whether --white is
+                        --elo0 or --elo1 stronger (or --games games are played)
+  analyze               let --player pick a move in the --fen position
+  testsuite             run --player on the positions of the --suite EPD file, and tell
+                        how many of their 'bm' (best move) or 'am' (avoid move) it solves
+  explain               replay the move at --ply of the --game pgn with the player, budget
+                        and seed recorded in the pgn (or --player, for the games of
+                        other players), and report the moves it considered (the search
+                        tree is written next to the pgn as a dot file, for the players
+                        that have one)
+  uci                   run --player as a UCI engine on stdin/stdout
+  xboard                run --player as a CECP (xboard) engine on stdin/stdout
+  help                  print this message
+
+Options:
+  --white <spec>        white player (default: astar)
+  --black <spec>        black player (default: astarparl)
+  --player <spec>       player for analyze, testsuite, explain, uci and xboard (default: astar)
+  --time <duration>     time budget per move of the players whose spec doesn't set one
+                        (e.g. 100ms or 2s), time budget per position for testsuite (default: 1s)
+  --threads <n>         number of threads of the parallel players whose spec doesn't set one
+  --seed <n>            seed of the random choices of the players, printed when not given,
+                        so that a game or match can be replayed
+  --log-level <level>   all, trace, debug, info, warn or none (default: debug)
+  --log <path>          output path for the log (default: games/last_game.log)
+  --pgn <path>          output path for the pgn, which holds all the games of a match
+                        or tournament (default: games/last_game.pgn)
+  --entrant <spec>      tournament entrant, repeat for each entrant
+  --tc <control>        time control of the games of play, match, tournament and sprt, as
+                        [moves/]base[+increment] in seconds, e.g. 60+0.5 or 40/120: the players
+                        then manage their own time, and lose on time (default: no clocks)
+  --adjudicate-resign <n>,<plies>[,<min ply>]
+                        end the games of play, match, tournament and sprt as a loss for the side
+                        that is down by at least <n> pawns of material for <plies> plies in a row,
+                        from ply <min ply> on (default: 0)
+  --adjudicate-draw <n>,<plies>[,<min ply>]
+                        end these games as a draw when the material balance stays within <n>
+                        pawns for <plies> plies in a row, from ply <min ply> on (default: 80)
+  --openings <path>     start the games of match, tournament and sprt from the openings of
+                        this file (FEN/EPD lines, or PGN move prefixes), each opening being
+                        played twice with colors reversed
+  --games <n>           number of games for match (default: 10, or twice the number of openings),
+                        or per pairing for tournament (default: 10),
+                        maximum number of games for sprt (default: no maximum)
+  --elo0 <elo>          elo difference of the null hypothesis, for sprt (default: 0)
+  --elo1 <elo>          elo difference of the alternative hypothesis, for sprt (default: 10)
+  --alpha <p>           false positive rate, for sprt (default: 0.05)
+  --beta <p>            false negative rate, for sprt (default: 0.05)
+  --fen <fen>           position for analyze (default: the initial position)
+  --suite <path>        EPD file for testsuite
+  --game <path>         pgn file for explain
+  --ply <n>             ply (half-move) number, counted from 1, for explain
+  --round <round>       game to explain in a pgn that holds many, e.g. the one of a match,
+                        by its Round tag (default: the first game of the pgn)
+
+Players are given as specs, e.g. \"astarparl:time=100ms,threads=4\".
+The known players and their parameters are:";
+
+pub type CliErr = String;
+
+const DEFAULT_N_GAMES: u32 = 10;
+
+pub enum Command {
+    Play,
+    Match { n_games: u32 },
+    Tournament { entrants: Vec<String>, games_per_pairing: u32 },
+    Sprt { params: SprtParams, max_games: Option<u32> },
+    Analyze { fen: Option<String> },
+    TestSuite { suite_path: PathBuf },
+    Explain { game_path: PathBuf, ply: usize, round: Option<String> },
+    Uci,
+    Xboard,
+    Help,
+}
+
+pub struct Options {
+    pub white:        String,
+    pub black:        String,
+    pub player:       String,
+    pub time_budget:  Option<Duration>,
+    pub n_threads:    Option<ThreadCount>,
+    pub seed:         Option<play::Seed>,
+    pub log_level:    LogLevel,
+    pub log_path:     Option<PathBuf>,
+    pub pgn_path:     Option<PathBuf>,
+    pub openings:     Vec<Opening>,
+    pub arbiter:      play::Arbiter,
+}
+
+pub struct Cli {
+    pub command: Command,
+    pub options: Options,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            white:        String::from("astar"),
+            black:        String::from("astarparl"),
+            player:       String::from("astar"),
+            time_budget:  None,
+            n_threads:    None,
+            seed:         None,
+            log_level:    LogLevel::Debug,
+            log_path:     None,
+            pgn_path:     None,
+            openings:     Vec::new(),
+            arbiter:      play::Arbiter::default(),
+        }
+    }
+}
+
+/********** Parsing **********/
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Cli, CliErr> {
+    let command_name = args.next().ok_or("Missing command")?;
+
+    let mut options = Options::default();
+    /* command-specific options */
+    let mut n_games   = None;
+    let mut sprt      = SprtParams::default();
+    let mut entrants  = Vec::new();
+    let mut fen       = None;
+    let mut suite     = None;
+    let mut game_path = None;
+    let mut ply       = None;
+    let mut round     = None;
+
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", flag));
+
+        match flag.as_str() {
+            "--white"             => options.white = value()?,
+            "--black"             => options.black = value()?,
+            "--player"            => options.player = value()?,
+            "--time"              => options.time_budget = Some(registry::parse_duration(&value()?)?),
+            "--threads"           => options.n_threads = Some(parse_number(&value()?)?),
+            "--seed"              => options.seed = Some(parse_number(&value()?)?),
+            "--log-level"         => options.log_level = parse_log_level(&value()?)?,
+            "--log"               => options.log_path = Some(PathBuf::from(value()?)),
+            "--pgn"               => options.pgn_path = Some(PathBuf::from(value()?)),
+            "--openings"          => options.openings = openings::load_openings(&PathBuf::from(value()?))?,
+            "--tc"                => options.arbiter.time_control = Some(value()?.parse()?),
+            "--adjudicate-resign" => options.arbiter.adjudication.resign = Some(parse_threshold(&value()?, 0)?),
+            "--adjudicate-draw"   => options.arbiter.adjudication.draw = Some(parse_threshold(&value()?, play::DEFAULT_DRAW_MIN_PLY)?),
+            "--entrant"           => entrants.push(value()?),
+            "--games"             => n_games = Some(parse_number(&value()?)?),
+            "--elo0"              => sprt.elo0 = parse_number(&value()?)?,
+            "--elo1"              => sprt.elo1 = parse_number(&value()?)?,
+            "--alpha"             => sprt.alpha = parse_number(&value()?)?,
+            "--beta"              => sprt.beta = parse_number(&value()?)?,
+            "--fen"               => fen = Some(value()?),
+            "--suite"             => suite = Some(PathBuf::from(value()?)),
+            "--game"              => game_path = Some(PathBuf::from(value()?)),
+            "--ply"               => ply = Some(parse_number(&value()?)?),
+            "--round"             => round = Some(value()?),
+            _                     => return Err(format!("Unknown option \"{}\"", flag)),
+        }
+    }
+
+    let command = match command_name.as_str() {
+        "play"    => Command::Play,
+        "match"   => {
+            /* Give every opening the same chance */
+            let default_n_games = if options.openings.is_empty() { DEFAULT_N_GAMES }
+                                  else { 2 * options.openings.len() as u32 };
+            Command::Match { n_games: n_games.unwrap_or(default_n_games) }
+        }
+        "tournament" => Command::Tournament {
+            entrants,
+            games_per_pairing: n_games.unwrap_or(DEFAULT_N_GAMES),
+        },
+        "sprt"    => {
+            sprt.check()?;
+            Command::Sprt { params: sprt, max_games: n_games }
+        }
+        "analyze" => Command::Analyze { fen },
+        "testsuite" => Command::TestSuite {
+            suite_path: suite.ok_or("testsuite requires --suite")?,
+        },
+        "explain" => Command::Explain {
+            game_path: game_path.ok_or("explain requires --game")?,
+            ply:       ply.ok_or("explain requires --ply")?,
+            round,
+        },
+        "uci"     => Command::Uci,
+        "xboard"  => Command::Xboard,
+        "help" | "--help" | "-h" => Command::Help,
+        _         => return Err(format!("Unknown command \"{}\"", command_name)),
+    };
+
+    Ok(Cli {
+        command,
+        options
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, CliErr> {
+    s.parse::<T>()
+     .map_err(|_| format!("\"{}\" is not a valid number", s))
+}
+
+/* "<score>,<plies>[,<min ply>]", e.g. "9,10" or "0,20,60" */
+fn parse_threshold(s: &str, default_min_ply: usize) -> Result<play::Threshold, CliErr> {
+    let invalid = || format!("\"{}\" is not a valid threshold (expected <pawns>,<plies>[,<min ply>])", s);
+    let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+    let (score, plies, min_ply) = match parts.as_slice() {
+        [score, plies]          => (score, plies, None),
+        [score, plies, min_ply] => (score, plies, Some(min_ply)),
+        _                       => return Err(invalid()),
+    };
+
+    Ok(play::Threshold {
+        score:   parse_number(score)?,
+        plies:   parse_number(plies)?,
+        min_ply: match min_ply {
+            Some(min_ply) => parse_number(min_ply)?,
+            None          => default_min_ply,
+        },
+    })
+}
+
+fn parse_log_level(s: &str) -> Result<LogLevel, CliErr> {
+    match s {
+        "all"   => Ok(LogLevel::All),
+        "trace" => Ok(LogLevel::Trace),
+        "debug" => Ok(LogLevel::Debug),
+        "info"  => Ok(LogLevel::Info),
+        "warn"  => Ok(LogLevel::Warn),
+        "none"  => Ok(LogLevel::None),
+        _       => Err(format!("Unknown log level \"{}\"", s)),
+    }
+}
+
+/********** Players **********/
+
+pub fn usage() -> String {
+    format!("{}\n{}", USAGE, registry::describe_players())
+}
+
+/* A single player gets --seed as is, or a random seed if not given */
+pub fn make_player(spec: &str, options: &Options) -> Result<Box<dyn play::ChessPlayer>, CliErr> {
+    let spec = player_spec(spec, options)?;
+    match options.seed {
+        Some(seed) => spec.with_seed(seed).build(),
+        None       => spec.build(),
+    }
+}
+
+/* --time and --threads apply to the players whose spec doesn't set them */
+pub fn player_spec(spec: &str, options: &Options) -> Result<PlayerSpec, CliErr> {
+    let mut spec = spec.parse::<PlayerSpec>()?;
+
+    if let Some(time_budget) = options.time_budget {
+        spec = spec.with_default("time", format!("{}ms", time_budget.as_millis()));
+    }
+    if let Some(n_threads) = options.n_threads {
+        spec = spec.with_default("threads", n_threads.to_string());
+    }
+
+    Ok(spec)
+}
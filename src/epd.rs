@@ -0,0 +1,128 @@
+use chess::Board;
+use std::str::FromStr;
+
+/* Extended Position Description: the first four fields of a FEN,
+ * followed by operations, e.g.
+ *
+ *   r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - bm Qxf7#; id "mate in 1";
+ *
+ * Each operation is an opcode and its operands, ended by a semicolon.
+ * The move counters of a FEN are also accepted in place of the operations.
+ */
+
+pub type EpdErr = String;
+
+pub struct Operation {
+    pub opcode:   String,
+    pub operands: Vec<String>,  /* string operands are unquoted */
+}
+
+pub struct EpdRecord {
+    pub board:      Board,
+    pub operations: Vec<Operation>,
+}
+
+impl EpdRecord {
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter()
+                       .find(|op| op.opcode == opcode)
+                       .map(|op| op.operands.as_slice())
+    }
+}
+
+impl FromStr for EpdRecord {
+    type Err = EpdErr;
+
+    fn from_str(line: &str) -> Result<Self, EpdErr> {
+        let mut rest = line.trim();
+        let mut fields = Vec::new();
+        for _ in 0..4 {
+            rest = rest.trim_start();
+            let field_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fields.push(&rest[..field_len]);
+            rest = &rest[field_len..];
+        }
+
+        let board = parse_position(&fields.join(" "))?;
+        let operations = parse_operations(rest)
+                            .map_err(|reason| format!("{} in \"{}\"", reason, line.trim()))?;
+
+        Ok(EpdRecord {
+            board,
+            operations,
+        })
+    }
+}
+
+/* Only the first four fields are used: the move counters are optional */
+pub fn parse_position(s: &str) -> Result<Board, EpdErr> {
+    let fields: Vec<&str> = s.split_whitespace().take(4).collect();
+    Board::from_str(&fields.join(" "))
+        .map_err(|err| format!("Invalid position \"{}\": {}", s, err))
+}
+
+fn parse_operations(s: &str) -> Result<Vec<Operation>, EpdErr> {
+    let mut operations = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                skip_move_counters(&mut words);
+                if words.is_empty() {
+                    return Err(String::from("Empty operation"));
+                }
+                operations.push(Operation {
+                    opcode:   words.remove(0),
+                    operands: words.split_off(0),
+                });
+            }
+
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"')  => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c)    => word.push(c),
+                        None       => return Err(String::from("Unterminated string")),
+                    }
+                }
+                words.push(word);
+            }
+
+            c if c.is_whitespace() => { }
+
+            c => {
+                let mut word = String::from(c);
+                while let Some(next) = chars.peek() {
+                    if next.is_whitespace() || *next == ';' || *next == '"' {
+                        break;
+                    }
+                    word.push(chars.next().unwrap());
+                }
+                words.push(word);
+            }
+        }
+    }
+
+    /* A forgotten last semicolon is tolerated */
+    skip_move_counters(&mut words);
+    if !words.is_empty() {
+        operations.push(Operation {
+            opcode:   words.remove(0),
+            operands: words,
+        });
+    }
+
+    Ok(operations)
+}
+
+/* The move counters of a FEN are not an operation */
+fn skip_move_counters(words: &mut Vec<String>) {
+    let n_counters = words.iter()
+                          .take_while(|word| word.parse::<u32>().is_ok())
+                          .count();
+    words.drain(..n_counters);
+}
@@ -6,7 +6,7 @@ use chess::{Board, BoardStatus, Color, Piece, Square};
 //const MIN_SCORE: Score = -1000.0;
 pub type Score = i16;
 
-const DRAW_SCORE:    Score = 0;
+pub const DRAW_SCORE:    Score = 0;
 const WINNING_SCORE: Score = Score::MAX;
 const LOSING_SCORE:  Score = Score::MIN;
 //const MAX_SCORE: Score = 1000.0;
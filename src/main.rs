@@ -8,32 +8,118 @@ mod pgn;
 mod eval;
 mod play;
 mod utils;
+mod uci;
+mod cecp;
+mod cli;
+mod arena;
+mod epd;
+mod testsuite;
 
-use chess::{Board, BoardStatus, Color, ChessMove};
-use play::Game;
+use chess::{Board, Color, ChessMove};
+use play::{ChessPlayer, Decision, Game, PositionHistory, Seed, Termination};
+use play::registry::PlayerSpec;
+use pgn::AnnotatedGame;
+use pgn::database::GameReader;
+use arena::tournament::Tournament;
+use arena::sprt::{Sprt, SprtParams};
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::io::Write;
 use std::io;
+use std::str::FromStr;
 use std::time::Duration;
+use cli::{Command, Options};
 
-#[allow(unused_variables)]
 fn main() {
-    //let white = play::montecarlo::basic_monte_carlo1();
-    //let white = play::exhaustive::exhaustive_search_player(2);
-    //let black = play::evaldriven::classic_eval_player();
-    let white = play::astar::astar_player(Duration::from_millis(100));
-    let black = play::astar::astarparl::parallel_player(Duration::from_millis(100), 4);
+    let cli = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(cli)     => cli,
+        Err(reason) => {
+            eprintln!("{}\n\n{}", reason, cli::usage());
+            std::process::exit(1);
+        }
+    };
+    let options = &cli.options;
+
+    match cli.command {
+        Command::Play => play_a_game(options),
+
+        Command::Match { n_games } => play_a_match(options, n_games),
+
+        Command::Tournament { entrants, games_per_pairing } =>
+            run_a_tournament(options, &entrants, games_per_pairing),
+
+        Command::Sprt { params, max_games } => run_sprt(options, params, max_games),
+
+        Command::Analyze { fen } => {
+            let board = match fen {
+                Some(fen) => Board::from_str(&fen).unwrap_or_else(|err| {
+                    eprintln!("Invalid fen \"{}\": {}", fen, err);
+                    std::process::exit(1);
+                }),
+                None      => Board::default(),
+            };
+            let player = player_or_exit(&options.player, options);
+            analyze_position(player, &board, options.log_level);
+        }
+
+        Command::TestSuite { suite_path } => run_test_suite(options, &suite_path),
+
+        Command::Explain { game_path, ply, round } => explain_move_from_prev_game(options, &game_path, ply, round.as_deref()),
+
+        Command::Uci => {
+            let mut engine = player_or_exit(&options.player, options);
+            /* stdout is reserved for the protocol */
+            let mut logger = engine_logger(options);
+            uci::run_uci(&mut engine, &mut *logger)
+                .expect("Lost the connection with the UCI GUI");
+        }
+
+        Command::Xboard => {
+            let mut engine = player_or_exit(&options.player, options);
+            let mut logger = engine_logger(options);
+            cecp::run_cecp(&mut engine, &mut *logger)
+                .expect("Lost the connection with the xboard GUI");
+        }
+
+        Command::Help => println!("{}", cli::usage()),
+    }
+}
 
-    let log_level = logging::LogLevel::Debug;
+const EXPLAIN_TIME_BUDGET: Duration = Duration::from_millis(100);
 
-    play_a_game(white, black, log_level);
-    /*explain_move_from_prev_game(
-        white,
-        &Path::new("games/debug_astar_201115/debug_move_17.pgn"),
-        Color::White,
-        17,
-        log_level);*/
+fn player_or_exit(name: &str, options: &Options) -> Box<dyn ChessPlayer> {
+    cli::make_player(name, options)
+        .unwrap_or_else(|reason| exit_on_bad_spec(reason))
+}
+
+/* The seed all the others are derived from, printed so that it can be given back with --seed */
+fn master_seed(options: &Options) -> Seed {
+    let seed = options.seed.unwrap_or_else(play::random_seed);
+    println!("Seed: {}", seed);
+    seed
+}
+
+fn spec_or_exit(name: &str, options: &Options) -> PlayerSpec {
+    cli::player_spec(name, options)
+        .unwrap_or_else(|reason| exit_on_bad_spec(reason))
+}
+
+fn exit_on_bad_spec(reason: cli::CliErr) -> ! {
+    eprintln!("{}\nSee 'hakarl help' for the list of players", reason);
+    std::process::exit(1);
+}
+
+/* Engines only log if explicitly asked to */
+fn engine_logger(options: &Options) -> Box<play::Logger> {
+    match &options.log_path {
+        Some(log_path) => Box::new(open_log(log_path, options.log_level)),
+        None           => Box::new(logging::ignore_all()),
+    }
+}
+
+fn open_log(log_path: &Path, log_level: logging::LogLevel) -> logging::AnyLogger<File> {
+    logging::log_to_file(log_path, log_level)
+        .unwrap_or_else(|_| panic!("Couldn't open file {}", log_path.display()))
 }
 
 /***********  PLAY **********/
@@ -41,29 +127,44 @@ fn main() {
 const LOG_FILE_PATH: &str = "games/last_game.log";
 const PGN_FILE_PATH: &str = "games/last_game.pgn";
 
-#[allow(dead_code)]
-fn play_a_game<P1, P2>(mut white: P1, mut black: P2, log_level: logging::LogLevel)
-    where
-        P1: play::ChessPlayer,
-        P2: play::ChessPlayer
-{
-    let mut game_logger = logging::log_to_file(&Path::new(LOG_FILE_PATH), log_level)
-                                    .expect(&format!("Couldn't open file {}", LOG_FILE_PATH));
+fn log_path(options: &Options) -> PathBuf {
+    options.log_path.clone().unwrap_or(PathBuf::from(LOG_FILE_PATH))
+}
 
-    let game = play::play_game(&mut white, &mut black, &mut game_logger);
+fn pgn_path(options: &Options) -> PathBuf {
+    options.pgn_path.clone().unwrap_or(PathBuf::from(PGN_FILE_PATH))
+}
+
+fn play_a_game(options: &Options) {
+    let seed = master_seed(options);
+    let white_spec = spec_or_exit(&options.white, options).with_seed(play::derive_seed(seed, 0));
+    let black_spec = spec_or_exit(&options.black, options).with_seed(play::derive_seed(seed, 1));
+    let mut white = white_spec.build().unwrap_or_else(|reason| exit_on_bad_spec(reason));
+    let mut black = black_spec.build().unwrap_or_else(|reason| exit_on_bad_spec(reason));
+
+    let log_path = log_path(options);
+    let pgn_path = pgn_path(options);
+    let mut game_logger = open_log(&log_path, options.log_level);
+    let metadata = arena::game_metadata("hakarl game", None, &white_spec, &black_spec);
+    for (name, value) in metadata.tags.iter() {
+        info!(&mut game_logger, "{}: {}", name, value);
+    }
 
-    /* Print the move list in pgn format */
-    let pgn_format = pgn::basic_pgn(&game.moves);
+    let mut game = play::play_game(&mut white, &mut black, &options.arbiter, &mut game_logger);
+    game.metadata = metadata;
+
+    /* Print the game in pgn format */
+    let pgn_format = pgn::game_pgn(&game);
     println!("{}", pgn_format);
 
-    let mut pgn_file = open_file_for_write(&Path::new(PGN_FILE_PATH));
+    let mut pgn_file = open_file_for_write(&pgn_path);
     let pgn_written = write!(pgn_file, "{}", pgn_format);
 
     /* Print the result of the game */
     print_end_of_game(&game);
-    println!("The explanation of the moves can be found in '{}'", LOG_FILE_PATH);
+    println!("The explanation of the moves can be found in '{}'", log_path.display());
     match pgn_written {
-        Ok(_)       => println!("The pgn can also be found in '{}'", PGN_FILE_PATH),
+        Ok(_)       => println!("The pgn can also be found in '{}'", pgn_path.display()),
         Err(reason) => println!("The pgn could not be written to a file: {}", reason),
     };
 }
@@ -76,74 +177,399 @@ fn open_file_for_write(path: &Path) -> File {
 }
 
 fn print_end_of_game(game: &Game) {
-    match game.final_board.status() {
-        BoardStatus::Checkmate => println!("Player {:?} wins!", game.winner().unwrap()),
-        BoardStatus::Stalemate => println!("The game is a draw!"),
-        BoardStatus::Ongoing   => println!("Maximum number of moves reached")
+    match (game.termination(), game.winner()) {
+        (Some(Termination::MoveLimit), _) | (None, _) => println!("Maximum number of moves reached"),
+        (Some(termination), Some(winner))             => println!("Player {:?} wins by {}!", winner, termination),
+        (Some(termination), None)                     => println!("The game is a draw by {}!", termination),
     }
 }
 
-/***********  EXPLAIN **********/
+/***********  MATCH **********/
+
+/* A match is a tournament between two players */
+fn play_a_match(options: &Options, n_games: u32) {
+    let entrants = vec![options.white.clone(), options.black.clone()];
+    run_a_tournament(options, &entrants, n_games);
+}
+
+fn run_a_tournament(options: &Options, entrants: &[String], games_per_pairing: u32) {
+    let entrants = entrants.iter()
+                           .map(|spec| spec_or_exit(spec, options))
+                           .collect();
+    let tournament = Tournament {
+        event:        String::from("hakarl tournament"),
+        entrants,
+        games_per_pairing,
+        openings:     options.openings.clone(),
+        arbiter:      options.arbiter,
+        seed:         master_seed(options),
+    };
+
+    let log_path = log_path(options);
+    let pgn_path = pgn_path(options);
+    let mut match_logger = open_log(&log_path, options.log_level);
+    let mut pgn_file = open_file_for_write(&pgn_path);
+
+    let standings = arena::tournament::run_tournament(&tournament, &mut pgn_file, &mut match_logger, |report| {
+        print!("Game {} ({} vs {}): ", report.round, report.white, report.black);
+        print_end_of_game(report.game);
+    });
+    let standings = standings.unwrap_or_else(|reason| {
+        eprintln!("{}", reason);
+        std::process::exit(1);
+    });
+
+    println!();
+    for (first, second) in standings.pairings() {
+        let record = standings.record(first, second);
+        let elo = match arena::estimate_elo(&record) {
+            Some(elo) => format!("Elo {}", elo),
+            None      => String::from("Elo unknown"),
+        };
+        println!("{} vs {}: {} ({})", standings.names[first], standings.names[second], record, elo);
+    }
+
+    println!("\nStandings:");
+    for (rank, entrant) in standings.ranking().into_iter().enumerate() {
+        let total = standings.total(entrant);
+        println!("{:>3}. {:<30} {:>5.1}/{:<4} {}",
+                 rank + 1, standings.names[entrant], total.points(), total.n_games(), total);
+    }
+
+    println!("\nThe explanation of the moves can be found in '{}'", log_path.display());
+    println!("The games can be found in '{}'", pgn_path.display());
+}
+
+/***********  SPRT **********/
+
+fn run_sprt(options: &Options, params: SprtParams, max_games: Option<u32>) {
+    let sprt = Sprt {
+        event:        String::from("hakarl sprt"),
+        candidate:    spec_or_exit(&options.white, options),
+        baseline:     spec_or_exit(&options.black, options),
+        params,
+        max_games,
+        openings:     options.openings.clone(),
+        arbiter:      options.arbiter,
+        seed:         master_seed(options),
+    };
+    let (lower, upper) = params.bounds();
+    println!("SPRT of {} against {}: elo0 {}, elo1 {}, alpha {}, beta {}, bounds [{:.2}, {:.2}]",
+             sprt.candidate, sprt.baseline, params.elo0, params.elo1, params.alpha, params.beta, lower, upper);
 
-fn find_move_in_game(game: &play::Game, player: Color, turn: u16) -> Option<(Board, ChessMove)> {
-    let mut curr_board = game.init_board;
-    let mut curr_turn = 1;
-    for mv_ref in game.moves.iter() {
-        let curr_player = curr_board.side_to_move();
-        if curr_turn == turn && curr_player == player {
-            return Some((curr_board, *mv_ref));
+    let log_path = log_path(options);
+    let pgn_path = pgn_path(options);
+    let mut match_logger = open_log(&log_path, options.log_level);
+    let mut pgn_file = open_file_for_write(&pgn_path);
+
+    let outcome = arena::sprt::run_sprt(&sprt, &mut pgn_file, &mut match_logger, |report, state| {
+        println!("Game {} ({} vs {}): {}  {}  LLR {:.2}",
+                 report.round, report.white, report.black,
+                 pgn::result_token(report.game), state.record, state.llr);
+    });
+    let outcome = outcome.unwrap_or_else(|reason| {
+        eprintln!("{}", reason);
+        std::process::exit(1);
+    });
+
+    let elo = match arena::estimate_elo(&outcome.record) {
+        Some(elo) => format!("Elo {}", elo),
+        None      => String::from("Elo unknown"),
+    };
+    println!("\n{} vs {}: {} ({}), {}",
+             sprt.candidate, sprt.baseline, outcome.record, elo, outcome.verdict);
+    println!("The explanation of the moves can be found in '{}'", log_path.display());
+    println!("The games can be found in '{}'", pgn_path.display());
+}
+
+/***********  ANALYZE **********/
+
+fn analyze_position<P: play::ChessPlayer>(mut player: P, board: &Board, log_level: logging::LogLevel) {
+    let mut logger = logging::log_to(io::stdout(), log_level);
+    let (decision, search_info) = player.decide_with_info(board, &PositionHistory::new(board), &mut logger);
+    let best_move = match decision {
+        Decision::Play(mv) => mv,
+        _                  => {
+            eprintln!("The player gave no move");
+            std::process::exit(1);
         }
-        else {
-            curr_board = curr_board.make_move_new(*mv_ref);
-            if curr_player == Color::Black {
-                curr_turn += 1;
+    };
+
+    println!("Best move: {}", best_move);
+    if let Some(info) = search_info {
+        if let Some(score) = info.score {
+            println!("  score: {}", score);
+        }
+        let line: Vec<_> = info.best_line.iter().map(|mv| format!("{}", mv)).collect();
+        println!("  best line: {}", line.join(" "));
+        println!("  {} nodes, depth {}, in {}ms", info.nodes, info.depth, info.duration.as_millis());
+    }
+}
+
+/***********  TEST SUITE **********/
+
+const TEST_SUITE_TIME_BUDGET: Duration = Duration::from_secs(1);
+
+fn run_test_suite(options: &Options, suite_path: &Path) {
+    let spec = spec_or_exit(&options.player, options).with_seed(master_seed(options));
+    /* A bad spec would fail every position, rather than only the ones the player fails */
+    spec.build().unwrap_or_else(|reason| exit_on_bad_spec(reason));
+    let time_budget = options.time_budget.unwrap_or(TEST_SUITE_TIME_BUDGET);
+    let suite = testsuite::load_suite(suite_path).unwrap_or_else(|reason| {
+        eprintln!("{}", reason);
+        std::process::exit(1);
+    });
+    let mut logger = engine_logger(options);
+
+    println!("Running {} on {} positions, {}ms per position",
+             spec, suite.len(), time_budget.as_millis());
+
+    let summary = testsuite::run_suite(&spec, &suite, time_budget, &mut *logger, |position, result| {
+        let played = match &result.played {
+            Ok(played)  => played,
+            Err(reason) => {
+                println!("{}: failed ({}, {})", position.id, position.expected, reason);
+                return;
+            }
+        };
+        let verdict = match result.time_to_solution {
+            Some(time) => format!("solved in {}ms", time.as_millis()),
+            None       => String::from("unsolved"),
+        };
+        let mut details = String::new();
+        if let Some(info) = &result.info {
+            if let Some(score) = info.score {
+                details.push_str(&format!(", score {}", score));
             }
+            let line: Vec<_> = info.best_line.iter().map(|mv| format!("{}", mv)).collect();
+            details.push_str(&format!(", line {}", line.join(" ")));
         }
+        println!("{}: {} ({}, played {}{})", position.id, verdict, position.expected, played, details);
+    });
+
+    let percent = 100. * summary.n_solved as f64 / std::cmp::max(summary.n_positions, 1) as f64;
+    print!("\nSolved {}/{} ({:.0}%)", summary.n_solved, summary.n_positions, percent);
+    match summary.average_time() {
+        Some(time) => println!(", average time to solution {}ms", time.as_millis()),
+        None       => println!(),
     }
-    None
 }
 
-#[allow(dead_code)]
-fn explain_move_from_prev_game(
-    mut player:   play::astar::AStar,
-    pgn_to_load:  &Path,
-    debug_player: Color,
-    turn:         u16,
-    log_level:    logging::LogLevel)
+/***********  EXPLAIN **********/
+
+/* The position before the move of the given ply, counted from 1, and
+ * the history that led to it
+ */
+fn find_move_in_game(game: &play::Game, ply: usize) -> Option<(Board, PositionHistory, ChessMove)> {
+    let mv = *game.moves.get(ply.checked_sub(1)?)?;
+    let mut board = game.init_board;
+    let mut history = PositionHistory::new(&board);
+    for prev_mv in &game.moves[..ply - 1] {
+        history.push(&board, *prev_mv);
+        board = board.make_move_new(*prev_mv);
+    }
+    Some((board, history, mv))
+}
+
+/* The game with the given Round tag, or the first one. The malformed
+ * games of the pgn are reported and skipped.
+ */
+fn find_game_in_pgn(pgn_to_load: &Path, round: Option<&str>) -> Result<AnnotatedGame, String> {
+    let reader = GameReader::open(pgn_to_load)
+                    .map_err(|reason| format!("Couldn't read {}: {}", pgn_to_load.display(), reason))?;
+
+    for game in reader {
+        match game {
+            Ok(game) if round.is_none_or(|round| game.game.metadata.round == round) => return Ok(game),
+            Ok(_)        => { }
+            Err(reason)  => eprintln!("Skipped a game: {}", reason),
+        }
+    }
+
+    match round {
+        Some(round) => Err(format!("No game of round {} in {}", round, pgn_to_load.display())),
+        None        => Err(format!("No game in {}", pgn_to_load.display())),
+    }
+}
+
+/* Tries to find the move of the game again, when the search of the player
+ * depends on time: the seed is the same, but the search may go further or
+ * not as far under the same time budget.
+ */
+const EXPLAIN_MAX_TRIES: u32 = 100;
+
+/* The player of the given color as recorded in the tags of the game: its
+ * spec, budget and seed. The games played elsewhere, or by a person or
+ * another engine, are explained by --player instead.
+ */
+fn recorded_player_spec(options: &Options, metadata: &play::GameMetadata, color: Color) -> PlayerSpec {
+    let (name, tag_prefix) = match color {
+        Color::White => (&metadata.white, "White"),
+        Color::Black => (&metadata.black, "Black"),
+    };
+    let recorded_tag = |suffix: &str| metadata.tag(&format!("{}{}", tag_prefix, suffix));
+
+    let spec = match name.parse::<PlayerSpec>() {
+        Ok(spec) if !spec.is_external() && spec.build().is_ok() => spec,
+        _ => {
+            println!("The pgn doesn't name a player that can explain its moves, {} explains them instead", options.player);
+            spec_or_exit(&options.player, options)
+        }
+    };
+    let spec = arena::BUDGET_TAGS.iter()
+                                 .filter_map(|(key, suffix)| recorded_tag(suffix).map(|value| (key, value)))
+                                 .fold(spec, |spec, (key, value)| spec.with_default(key, String::from(value)))
+                                 .with_default("time", format!("{}ms", EXPLAIN_TIME_BUDGET.as_millis()));
+
+    match recorded_tag("Seed").and_then(|value| value.parse::<Seed>().ok()) {
+        Some(seed) => spec.with_seed(seed),
+        None       => {
+            println!("No {}Seed tag in the pgn, the move may not be found again", tag_prefix);
+            spec
+        }
+    }
+}
+
+/* The player is asked for its moves of the game again, in the same order,
+ * so that it reuses its searches as it did during the game. The moves of
+ * the opening were not its own.
+ */
+fn replay_player_until(
+    player:        &mut dyn ChessPlayer,
+    game:          &Game,
+    opening_plies: usize,
+    ply:           usize,
+    logger:        &mut play::Logger)
+    -> (ChessMove, Option<play::Explanation>)
 {
-    //let mut logger = logging::log_to_file(&Path::new(LOG_FILE_PATH), log_level)
-                                    //.expect(&format!("Couldn't open file {}", LOG_FILE_PATH));
-    let game_pgn = std::fs::read_to_string(pgn_to_load).unwrap();
-    let full_game = pgn::read_pgn(&game_pgn).unwrap();
-    let (debug_board, debug_mv) = find_move_in_game(&full_game, debug_player, turn).unwrap();
+    let mut board = game.init_board;
+    let mut history = PositionHistory::new(&board);
+    let color = match (ply - 1) % 2 {
+        0 => game.init_board.side_to_move(),
+        _ => !game.init_board.side_to_move(),
+    };
+    let mut replay_logger = logging::ignore_all();
+    for (idx, mv) in game.moves[..ply - 1].iter().enumerate() {
+        if idx >= opening_plies && board.side_to_move() == color {
+            player.decide_with_info(&board, &history, &mut replay_logger);
+        }
+        history.push(&board, *mv);
+        board = board.make_move_new(*mv);
+    }
+    player.pick_move_with_explanation(&board, &history, logger)
+}
 
-    let mut logger = logging::log_to(io::stdout(), log_level);
-    let max_retries = 2000;
-    for try_count in 0..max_retries {
-        use play::DebugPlayer;
-        let search_tree = player.compute_move(&debug_board, &mut logger);
-        let played_mv = player.best_move(&search_tree);
+fn explain_move_from_prev_game(options: &Options, pgn_to_load: &Path, ply: usize, round: Option<&str>) {
+    let exit_with = |reason: String| -> ! {
+        eprintln!("{}", reason);
+        std::process::exit(1);
+    };
 
-        //let played_mv = player.write_res_tree(&debug_board, &mut logger, &mut dot_file).unwrap();
+    let annotated_game = find_game_in_pgn(pgn_to_load, round)
+                            .unwrap_or_else(|reason| exit_with(reason));
+    let full_game = &annotated_game.game;
+    let (debug_board, debug_history, debug_mv) = find_move_in_game(full_game, ply)
+                                     .unwrap_or_else(|| exit_with(format!("The game has no ply {}, it has {} plies",
+                                                                          ply, full_game.moves.len())));
+
+    let metadata = &full_game.metadata;
+    let spec = recorded_player_spec(options, metadata, debug_board.side_to_move());
+    let opening_plies = metadata.tag(arena::OPENING_PLIES_TAG)
+                                .and_then(|value| value.parse::<usize>().ok())
+                                .unwrap_or(0);
+    if ply <= opening_plies {
+        println!("The move of ply {} is part of the opening, the player didn't choose it", ply);
+    }
+
+    let mut logger = logging::log_to(io::stdout(), options.log_level);
+    if spec.is_reproducible() && spec.seed().is_some() {
+        /* With the same seed and budget, the player plays the same moves */
+        let mut player = spec.build().unwrap_or_else(|reason| exit_on_bad_spec(reason));
+        let (played_mv, explanation) = replay_player_until(&mut player, full_game, opening_plies, ply, &mut logger);
+        let explanation = explanation.unwrap_or_else(|| exit_with(format!("Player {} can't explain its moves", spec)));
 
         if played_mv == debug_mv {
-            use play::astar;
             println!("Found the same move! ({})", debug_mv);
-            let dot_graph = astar::build_dot_graph_from(&player, &search_tree);
+        }
+        else {
+            println!("Not the same move, explaining {} instead of {}", played_mv, debug_mv);
+        }
+        report_explanation(&annotated_game, &debug_board, ply, &explanation, pgn_to_load);
+        return;
+    }
 
-            let dot_path = pgn_to_load.with_extension("dot");
-            let mut dot_file = open_file_for_write(&dot_path);
-            match dot_graph.write_to(&mut dot_file) {
-                Ok(_) => println!("Written to {:?}", dot_path),
-                Err(e) => println!("{:?}", e)
-            }
-                //.map(|_| finalize(&search_tree, self.eval, self.time_budget, logger))
-            return;
+    println!("The search of {} depends on time, the move may take a few tries to be found again", spec);
+    for try_count in 1..=EXPLAIN_MAX_TRIES {
+        /* A new player for each try, as players may reuse their previous search */
+        let mut player = spec.build().unwrap_or_else(|reason| exit_on_bad_spec(reason));
+        let (played_mv, explanation) = player.pick_move_with_explanation(&debug_board, &debug_history, &mut logger);
+        let explanation = explanation.unwrap_or_else(|| exit_with(format!("Player {} can't explain its moves", spec)));
+
+        if played_mv == debug_mv {
+            println!("Found the same move! ({})", debug_mv);
         }
-        else if try_count < max_retries {
-            println!("Not the same move, retrying... ({})", try_count+1);
+        else if try_count < EXPLAIN_MAX_TRIES {
+            println!("Not the same move ({} instead of {}), retrying... ({})", played_mv, debug_mv, try_count);
+            continue;
+        }
+        else {
+            println!("Did not manage to find the same move, explaining {} instead of {}", played_mv, debug_mv);
         }
+        report_explanation(&annotated_game, &debug_board, ply, &explanation, pgn_to_load);
+        return;
+    }
+}
+
+/* The search tree, for the players that have one, is written next to the pgn */
+fn report_explanation(annotated_game: &AnnotatedGame, board: &Board, ply: usize, explanation: &play::Explanation, pgn_to_load: &Path) {
+    print_explanation(board, ply, explanation);
+    print_pgn_annotations(annotated_game, board, ply);
+    if let Some(dot_graph) = &explanation.dot_graph {
+        let dot_path = pgn_to_load.with_extension("dot");
+        let mut dot_file = open_file_for_write(&dot_path);
+        match dot_graph.write_to(&mut dot_file) {
+            Ok(_)  => println!("The search tree was written to {}", dot_path.display()),
+            Err(e) => println!("The search tree could not be written: {:?}", e),
+        }
+    }
+}
+
+/* What the pgn tells about the move of the given ply, counted from 1:
+ * its comments, NAGs and the variations played instead
+ */
+fn print_pgn_annotations(annotated_game: &AnnotatedGame, board: &Board, ply: usize) {
+    let annotated = &annotated_game.main_line.moves[ply - 1];
+    if annotated.nags.is_empty() && annotated.comments.is_empty() && annotated.variations.is_empty() {
+        return;
+    }
+
+    let move_line = pgn::Line {
+        comments: Vec::new(),
+        moves:    vec![annotated.clone()],
+    };
+    println!("In the pgn: {}", pgn::line_text(board, annotated_game.turn_of(ply - 1), &move_line));
+}
+
+fn print_explanation(board: &Board, ply: usize, explanation: &play::Explanation) {
+    let format_line = |line: &[ChessMove]| line.iter()
+                                               .map(|mv| format!("{}", mv))
+                                               .collect::<Vec<_>>()
+                                               .join(" ");
+    let info = &explanation.info;
+
+    println!("\nPly {}, {:?} to move: {}", ply, board.side_to_move(), board);
+    println!("Search: {} nodes, depth {}, in {}ms", info.nodes, info.depth, info.duration.as_millis());
+    if let Some(score) = info.score {
+        println!("Score: {}", score);
+    }
+    println!("Best line: {}", format_line(&info.best_line));
+
+    println!("Candidates (best first):");
+    println!("  {:<7} {:>6} {:>8}  rating / line", "move", "score", "nodes");
+    for candidate in explanation.candidates.iter() {
+        let score = candidate.score.map_or(String::from("-"), |score| score.to_string());
+        let nodes = candidate.nodes.map_or(String::from("-"), |nodes| nodes.to_string());
+        println!("  {:<7} {:>6} {:>8}  {} / {}",
+                 candidate.mv.to_string(), score, nodes, candidate.rating, format_line(&candidate.best_line));
     }
-    println!("Did not manage to find the same move, giving up");
-    //assert_eq!(played_mv, debug_mv);
 }
@@ -1,19 +1,24 @@
-use chess::{self, ChessMove, Piece, Board, Color, Square, File};
+use chess::{self, ChessMove, Piece, Board, BoardStatus, Color, Square, File, Rank, MoveGen};
+use crate::eval;
 use crate::play;
-use std::{str, convert};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/********** WRITE **********/
+pub mod database;
 
-fn moved_piece(board: &Board, mv: ChessMove) -> Piece {
-    board.piece_on(mv.get_source()).unwrap()
-}
+/********** WRITE **********/
 
 fn piece_fmt(piece: Piece) -> String {
     piece.to_string(Color::White)
 }
 
-fn pos_fmt(sq: Square) -> String {
-    format!("{}", sq)
+fn file_fmt(file: File) -> char {
+    (b'a' + file.to_index() as u8) as char
+}
+
+fn rank_fmt(rank: Rank) -> char {
+    (b'1' + rank.to_index() as u8) as char
 }
 
 fn promote_fmt(mv: ChessMove) -> Option<String> {
@@ -29,235 +34,467 @@ fn promote_fmt(mv: ChessMove) -> Option<String> {
     }
 }
 
-fn try_castle_format(fmt_mv: FmtMove) -> Option<String> {
-    let (mv, moved_piece) = fmt_mv;
-
-    if moved_piece == Piece::King && mv.get_source().get_file() == File::E {
-        /* Checking the rank is pointless, as the files check correspond
-         * to a 2-hop or 3-hop move, which can only be done via castling
-         */
-        match mv.get_dest().get_file() {
-            File::G => Some(String::from("O-O")),
-            File::C => Some(String::from("O-O-O")),
-            _       => None
-        }
-
+fn castle_fmt(mv: ChessMove) -> String {
+    if mv.get_dest().get_file() > mv.get_source().get_file() {
+        String::from("O-O")
     }
     else {
-        None
+        String::from("O-O-O")
     }
 }
 
-fn gen_move(fmt_mv: FmtMove) -> String {
-    /* Check for castle move, which has special format */
-    let castle_rep = try_castle_format(fmt_mv);
-    if castle_rep.is_some() {
-        return castle_rep.unwrap();
+/* The source file or rank (or both) are only given when another piece of
+ * the same kind could also move to the destination
+ */
+fn disambiguation_fmt(board: &Board, mv: ChessMove, piece: Piece) -> String {
+    let source = mv.get_source();
+    let rivals: Vec<Square> = MoveGen::new_legal(board)
+                                  .filter(|other| other.get_dest() == mv.get_dest()
+                                               && other.get_source() != source
+                                               && board.piece_on(other.get_source()) == Some(piece))
+                                  .map(|other| other.get_source())
+                                  .collect();
+
+    if rivals.is_empty() {
+        String::new()
     }
+    else if rivals.iter().all(|sq| sq.get_file() != source.get_file()) {
+        file_fmt(source.get_file()).to_string()
+    }
+    else if rivals.iter().all(|sq| sq.get_rank() != source.get_rank()) {
+        rank_fmt(source.get_rank()).to_string()
+    }
+    else {
+        format!("{}", source)
+    }
+}
 
-    let (mv, moved_piece) = fmt_mv;
-    /* TODO make this code use 'format' to append to the string directly? */
-    let source_rep = pos_fmt(mv.get_source());
-    let dest_rep   = pos_fmt(mv.get_dest());
-
-    let piece_rep = piece_fmt(moved_piece);
-
+fn regular_fmt(board: &Board, mv: ChessMove) -> String {
+    let source = mv.get_source();
+    let piece = board.piece_on(source).unwrap();
+    /* A pawn that changes file captures, even when the square is empty (en passant) */
+    let is_capture = board.piece_on(mv.get_dest()).is_some()
+                     || (piece == Piece::Pawn && source.get_file() != mv.get_dest().get_file());
+
+    /* Pawns have no letter, their captures tell the file they come from */
+    let piece_rep = match piece {
+        Piece::Pawn if is_capture => file_fmt(source.get_file()).to_string(),
+        Piece::Pawn               => String::new(),
+        _                         => piece_fmt(piece) + &disambiguation_fmt(board, mv, piece),
+    };
+    let capture_rep = if is_capture { "x" } else { "" };
     let promote_rep = promote_fmt(mv).unwrap_or_default();
 
-    format!("{}{}{}{}", piece_rep, source_rep, dest_rep, promote_rep)
+    format!("{}{}{}{}", piece_rep, capture_rep, mv.get_dest(), promote_rep)
 }
 
-type FmtMove = (ChessMove, Piece);
-
-fn gen_turn(white: FmtMove, black: Option<FmtMove>, turn: u8) -> String {
-    let white_rep = gen_move(white);
+fn check_fmt(board_after: &Board) -> &'static str {
+    if board_after.status() == BoardStatus::Checkmate {
+        "#"
+    }
+    else if board_after.checkers().popcnt() > 0 {
+        "+"
+    }
+    else {
+        ""
+    }
+}
 
-    let black_rep = match black {
-        Some(black_fmt) => gen_move(black_fmt),
-        None            => String::new()
-    };
+/* Standard Algebraic Notation, e.g. "e4", "Nbd2", "exd5", "O-O" or "e8=Q+" */
+fn gen_move(board: &Board, mv: ChessMove) -> String {
+    /* Castling has its own format */
+    let move_rep = if is_castle(board, mv) { castle_fmt(mv) } else { regular_fmt(board, mv) };
+    let check_rep = check_fmt(&board.make_move_new(mv));
 
-    return format!("{}. {} {} ", turn, white_rep, black_rep)
+    format!("{}{}", move_rep, check_rep)
 }
 
+/* Move numbers are written before white moves, and before black moves
+ * that don't follow the white move of their turn, e.g. after a comment
+ */
 pub struct PGNBuilder {
-    turn: u8,
-    white_move: Option<FmtMove>,
-    buffer: String
+    turn:         u32,
+    number_black: bool,  /* the next black move needs its number */
+    buffer:       String,
 }
 
 impl PGNBuilder {
     fn new() -> PGNBuilder {
+        PGNBuilder::from_turn(1)
+    }
+
+    fn from_turn(turn: u32) -> PGNBuilder {
         PGNBuilder {
-            turn: 1,
-            white_move: None,
-            buffer: String::new()
+            turn,
+            number_black: true,
+            buffer:       String::new(),
         }
     }
 
+    /* The board is the one the move is played on */
     fn push_move(&mut self, board: &Board, mv: ChessMove) {
-        match self.white_move {
-            Some(white_move) => {
-                /* We now have both black and white moves, generate the turn */
-                let black_mv = mv;
-                let black_piece = moved_piece(board, black_mv);
-                let black_move = Some((black_mv, black_piece));
-
-                let turn_rep = gen_turn(white_move, black_move, self.turn);
-                self.buffer.push_str(&turn_rep);
-                self.turn += 1;
-                self.white_move = None;
+        let move_rep = gen_move(board, mv);
+
+        match board.side_to_move() {
+            Color::White => {
+                self.buffer.push_str(&format!("{}. {} ", self.turn, move_rep));
+                self.number_black = false;
             }
 
-            None => {
-                /* This is a white move. Cache it and wait for black move */
-                self.white_move = Some((mv, moved_piece(board, mv)));
+            Color::Black => {
+                if self.number_black {
+                    self.buffer.push_str(&format!("{}... ", self.turn));
+                }
+                self.buffer.push_str(&format!("{} ", move_rep));
+                self.turn += 1;
             }
         }
     }
 
-    fn to_string(&self) -> String {
-        match self.white_move {
-            Some(white_move) => {
-                /* Buffer is not up-to-date. Generate the last incomplete move and return */
-                let mut res = self.buffer.clone();
-                let black_move = None;
-                let turn_rep = gen_turn(white_move, black_move, self.turn);
-                res.push_str(&turn_rep);
-                return res;
+    fn push_nag(&mut self, nag: u8) {
+        self.buffer.push_str(&format!("${} ", nag));
+    }
+
+    fn push_comment(&mut self, comment: &str) {
+        self.buffer.push_str(&format!("{{{}}} ", comment));
+        self.number_black = true;
+    }
+
+    /* A line that could have been played instead of the last move. The
+     * board is the one that move was played on, the line stops at the first
+     * illegal move.
+     */
+    fn push_variation(&mut self, board: &Board, line: &[ChessMove]) {
+        let last_turn = if board.side_to_move() == Color::Black { self.turn - 1 } else { self.turn };
+        let mut variation = PGNBuilder::from_turn(last_turn);
+        let mut board = *board;
+        for mv in line.iter() {
+            if !board.legal(*mv) {
+                break;
             }
+            variation.push_move(&board, *mv);
+            board = board.make_move_new(*mv);
+        }
 
-            None => {
-                /* Buffer is up-to-date. Just copy it */
-                return self.buffer.clone();
+        self.push_nested(&variation);
+    }
+
+    /* A line of a move tree, with its annotations and its own variations */
+    fn push_line(&mut self, board: &Board, line: &Line) {
+        for comment in line.comments.iter() {
+            self.push_comment(comment);
+        }
+
+        let mut board = *board;
+        for annotated in line.moves.iter() {
+            let turn = self.turn;
+            self.push_move(&board, annotated.mv);
+            for nag in annotated.nags.iter() {
+                self.push_nag(*nag);
+            }
+            for comment in annotated.comments.iter() {
+                self.push_comment(comment);
             }
+            for variation in annotated.variations.iter() {
+                let mut nested = PGNBuilder::from_turn(turn);
+                nested.push_line(&board, variation);
+                self.push_nested(&nested);
+            }
+            board = board.make_move_new(annotated.mv);
         }
     }
-}
 
-pub fn basic_pgn(move_list: &[ChessMove]) -> String {
-    let mut board = Board::default();
-    let mut pgn_fmt = PGNBuilder::new();
+    fn push_nested(&mut self, variation: &PGNBuilder) {
+        self.buffer.push_str(&format!("({}) ", variation.to_string().trim_end()));
+        self.number_black = true;
+    }
 
-    for mv_ref in move_list {
-        let mv = mv_ref.clone();
-        pgn_fmt.push_move(&board, mv);
-        board = board.make_move_new(mv);
+    fn to_string(&self) -> String {
+        self.buffer.clone()
     }
+}
 
-    return pgn_fmt.to_string();
+/* Scores are for the player to move, evaluations for white: in pawns, or
+ * "#n" when a side mates in n moves (negative if black mates)
+ */
+fn eval_fmt(score: eval::Score, mover: Color, line_len: usize) -> String {
+    /* The best line ends in the mate for winning and losing scores */
+    let mate_in = (line_len.div_ceil(2)) as i32;
+    let sign = if mover == Color::White { 1 } else { -1 };
+
+    if score == eval::Score::MAX {
+        format!("#{}", sign * mate_in)
+    }
+    else if score == eval::Score::MIN {
+        format!("#{}", -sign * mate_in)
+    }
+    else {
+        format!("{:.2}", (sign * score as i32) as f32)
+    }
 }
 
-/********** READ **********/
+/* "h:mm:ss.s", as in the clock commands of comments */
+fn duration_fmt(duration: Duration) -> String {
+    let tenths = duration.as_millis() / 100;
+    let secs = tenths / 10;
+    format!("{}:{:02}:{:02}.{}", secs / 3600, secs / 60 % 60, secs % 60, tenths % 10)
+}
 
-/* Parser infrastructure */
+/* The search of the move and the clock after it, as commands in a comment:
+ * e.g. "[%eval 0.30] [%clk 0:04:58.3] [%emt 0:00:01.7] 1520 nodes"
+ */
+fn annotation_fmt(annotation: &play::MoveAnnotation, mover: Color) -> String {
+    let mut parts = Vec::new();
 
-type ParseErr = String;
-type ParseRes<T> = Result<T, ParseErr>;
+    if let Some(score) = annotation.search.as_ref().and_then(|search| search.score) {
+        let line_len = annotation.search.as_ref().map_or(0, |search| search.best_line.len());
+        parts.push(format!("[%eval {}]", eval_fmt(score, mover, line_len)));
+    }
+    if let Some(clock_left) = annotation.clock_left {
+        parts.push(format!("[%clk {}]", duration_fmt(clock_left)));
+    }
+    if let Some(search) = &annotation.search {
+        parts.push(format!("[%emt {}]", duration_fmt(search.duration)));
+        parts.push(format!("{} nodes", search.nodes));
+    }
 
-struct ParsedPrefix<'a, T> {
-    value:   T,
-    rem_str: &'a str
+    parts.join(" ")
 }
 
-impl<'a, T> ParsedPrefix<'a, T> {
-    fn as_full_parse(self) -> ParseRes<T> {
-        if self.rem_str.is_empty() {
-            Ok(self.value)
-        }
-        else {
-            Err(format!("Not at end of string, the following text remains: \"{}\"", self.rem_str))
+/* The moves, each followed by its annotation and by the principal
+ * variation of the engine that played it, then how the game ended when
+ * the Termination tag doesn't tell enough
+ */
+fn move_text(game: &play::Game) -> String {
+    let mut board = game.init_board;
+    let mut pgn_fmt = PGNBuilder::new();
+
+    for (ply, mv) in game.moves.iter().enumerate() {
+        pgn_fmt.push_move(&board, *mv);
+
+        if let Some(annotation) = game.annotation(ply) {
+            pgn_fmt.push_comment(&annotation_fmt(annotation, board.side_to_move()));
+
+            /* A line of a single move tells nothing that the move doesn't */
+            let best_line = annotation.search.as_ref().map_or(&[][..], |search| &search.best_line[..]);
+            if best_line.len() > 1 {
+                pgn_fmt.push_variation(&board, best_line);
+            }
         }
+
+        board = board.make_move_new(*mv);
+    }
+
+    if let Some(comment) = adjudication_comment(game) {
+        pgn_fmt.push_comment(&comment);
     }
+
+    return pgn_fmt.to_string();
 }
 
-trait Parse: Sized {
-    fn parse<'a>(s: &'a str) -> ParseRes<ParsedPrefix<'a, Self>>;
+/* The Termination tag says "adjudication" for both of its rules */
+fn adjudication_comment(game: &play::Game) -> Option<String> {
+    match game.termination()? {
+        play::Termination::Adjudication(Some(winner)) =>
+            Some(format!("{:?} wins by adjudication: {:?} is too far behind in material", winner, !winner)),
+        play::Termination::Adjudication(None) =>
+            Some(String::from("Draw by adjudication: the material is balanced")),
+        _ => None,
+    }
 }
 
-struct Parser<'a> {
-    rem_str: &'a str
+/* The move text of a line of a move tree, starting at the given turn */
+pub fn line_text(board: &Board, turn: u32, line: &Line) -> String {
+    let mut pgn_fmt = PGNBuilder::from_turn(turn);
+    pgn_fmt.push_line(board, line);
+    pgn_fmt.to_string().trim_end().to_string()
 }
 
-impl<'a> Parser<'a> {
-    fn new(s: &'a str) -> Self {
-        Parser {
-            rem_str: s
-        }
+/* Result token, as found at the end of the move text and in the Result tag */
+pub fn result_token(game: &play::Game) -> &'static str {
+    if !game.is_over() {
+        /* interrupted games (e.g. move limit) have no result */
+        return "*";
     }
 
-    fn parse<T: Parse>(&mut self) -> ParseRes<T> {
-        T::parse(self.rem_str)
-            .map(|ParsedPrefix { value, rem_str: rem_after_parse }| {
-                self.rem_str = rem_after_parse;
-                value
-            })
-        /*match T::parse(self.rem_str) {
-            Ok(ParsedPrefix { value, rem_str: rem_after_parse }) => {
-                self.rem_str = rem_after_parse;
-                Ok(value)
-            }
-            Err(err) => Err(err)
-        }*/
+    match game.winner() {
+        Some(Color::White) => "1-0",
+        Some(Color::Black) => "0-1",
+        None               => "1/2-1/2",
     }
+}
 
-    /*fn finalize<T>(self, value: T) -> ParseRes<T> {
-    }*/
+/* Value of the Termination tag */
+pub fn termination_token(game: &play::Game) -> &'static str {
+    match game.termination() {
+        Some(play::Termination::Adjudication(_)) => "adjudication",
+        Some(play::Termination::TimeForfeit(_))  => "time forfeit",
+        Some(play::Termination::MoveLimit)       => "unterminated",
+        None                                     => "unterminated",
+        Some(_)                                  => "normal",
+    }
+}
+
+pub type Tag = (String, String);
+
+fn tag_fmt(name: &str, value: &str) -> String {
+    format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/* Today's date, as written in the Date tag */
+pub fn date_today() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH)
+                                .map_or(0, |since_epoch| since_epoch.as_secs());
+
+    /* Civil date from the number of days since 1970-01-01, see
+     * http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+     */
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;  /* March is 0 */
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
 
-    fn finalize_prefix<T>(self, value: T) -> ParseRes<ParsedPrefix<'a, T>> {
-        Ok(ParsedPrefix {
-            value,
-            rem_str: self.rem_str
-        })
+/* A complete game: the Seven Tag Roster, the other tags of the metadata,
+ * the Termination tag (and TimeControl for timed games), then the move text
+ */
+pub fn game_pgn(game: &play::Game) -> String {
+    let result = result_token(game);
+    let metadata = &game.metadata;
+    let mut res = String::new();
+
+    let roster = [
+        ("Event",  metadata.event.as_str()),
+        ("Site",   metadata.site.as_str()),
+        ("Date",   metadata.date.as_str()),
+        ("Round",  metadata.round.as_str()),
+        ("White",  metadata.white.as_str()),
+        ("Black",  metadata.black.as_str()),
+        ("Result", result),
+    ];
+    for (name, value) in roster.iter() {
+        res.push_str(&tag_fmt(name, value));
+    }
+    for (name, value) in metadata.tags.iter() {
+        res.push_str(&tag_fmt(name, value));
     }
+    res.push_str(&tag_fmt("Termination", termination_token(game)));
+    if let Some(clock) = &game.clock {
+        res.push_str(&tag_fmt("TimeControl", &clock.control().to_string()));
+    }
+    if game.init_board != Board::default() {
+        res.push_str(&tag_fmt("SetUp", "1"));
+        res.push_str(&tag_fmt("FEN", &game.init_board.to_string()));
+    }
+    res.push('\n');
+
+    res.push_str(&move_text(game));
+    res.push_str(result);
+    res.push('\n');
+
+    res
+}
+
+/********** READ **********/
+
+/* Parser infrastructure */
+
+/* Why a pgn, or a move of it, couldn't be read */
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParseErrReason {
+    BadSyntax(String),              /* what is wrong with the token */
+    IllegalMove,
+    AmbiguousMove(Vec<ChessMove>),  /* the legal moves the token could stand for */
+    Unreadable(String),             /* the input itself, e.g. an I/O error */
+}
+
+/* Lines and columns are counted from 1, columns in chars */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TextPos {
+    pub line:   usize,
+    pub column: usize,
 }
 
-/* helper for elements that can be parsed as a single character */
-trait ParseChar: Sized {
-    fn parse_char(c: char) -> ParseRes<Self>;
+#[derive(Clone, PartialEq, Debug)]
+pub struct ParseErr {
+    pub reason: ParseErrReason,
+    pub token:  String,           /* the offending one */
+    pub pos:    Option<TextPos>,  /* of the token, None for a move read on its own */
+    pub ply:    Option<usize>,    /* of the move being read, counted from 1 */
 }
 
-impl<T: ParseChar> Parse for T {
-    fn parse<'a>(s: &'a str) -> ParseRes<ParsedPrefix<'a, T>> {
-        match s.chars().next() {
-            None             => Err(String::from("Input string is empty")),
-            Some(first_char) =>
-                T::parse_char(first_char)
-                    .map(|value| ParsedPrefix {
-                        value,
-                        rem_str: &s[1..]
-                    })
+type ParseRes<T> = Result<T, ParseErr>;
+
+impl ParseErr {
+    fn new(reason: ParseErrReason, token: &str) -> Self {
+        ParseErr {
+            reason,
+            token: String::from(token),
+            pos:   None,
+            ply:   None,
         }
     }
-}
 
-/* Define FromStr for anything that implements Parse */
-/* Can't do because of E[0210] ? */
-/*impl<T: Parse> str::FromStr for T {
-    type Err = ParseErr;
+    fn syntax(token: &str, what: &str) -> Self {
+        Self::new(ParseErrReason::BadSyntax(String::from(what)), token)
+    }
+
+    pub fn unreadable(reason: String) -> Self {
+        Self::new(ParseErrReason::Unreadable(reason), "")
+    }
 
-    fn from_str(s: &str) -> ParseRes<T> {
-        T::parse(s)
-            .and_then(|ParsedPrefix { value, rem_str }|
-                if rem_str.is_empty() {
-                    Ok(T)
-                }
-                else {
-                    Err(format!("Not at end of string, the following text remains: \"{}\"", rem_str))
-                }
-            )
+    fn at(mut self, pos: TextPos) -> Self {
+        self.pos = Some(pos);
+        self
     }
-}*/
 
-/* Data structures */
-/* TODO lift up and share with 'Write' part */
+    fn at_ply(mut self, ply: usize) -> Self {
+        self.ply = Some(ply);
+        self
+    }
 
-struct PGNPiece(chess::Piece);
-struct PGNSquare(chess::Square);
-struct PGNFile(chess::File);
-struct PGNRank(chess::Rank);
+    /* Positions in a text that starts at the given line of a bigger one */
+    pub fn shifted(mut self, first_line: usize) -> Self {
+        if let Some(pos) = self.pos.as_mut() {
+            pos.line += first_line - 1;
+        }
+        self
+    }
+}
 
-/* Parsing Piece */
+/* e.g. 'Line 12, column 5, ply 17: "Bxe5" is not a legal move' */
+impl fmt::Display for ParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.pos, self.ply) {
+            (Some(pos), Some(ply)) => write!(f, "Line {}, column {}, ply {}: ", pos.line, pos.column, ply)?,
+            (Some(pos), None)      => write!(f, "Line {}, column {}: ", pos.line, pos.column)?,
+            (None, Some(ply))      => write!(f, "Ply {}: ", ply)?,
+            (None, None)           => (),
+        }
+
+        match &self.reason {
+            ParseErrReason::BadSyntax(what)           => write!(f, "\"{}\" {}", self.token, what),
+            ParseErrReason::IllegalMove               => write!(f, "\"{}\" is not a legal move", self.token),
+            ParseErrReason::AmbiguousMove(candidates) =>
+                write!(f, "\"{}\" is ambiguous, it could be any of: {}",
+                       self.token,
+                       candidates.iter()
+                                 .map(|mv| format!("{}", mv))
+                                 .collect::<Vec<_>>()
+                                 .join(", ")),
+            ParseErrReason::Unreadable(reason)        => write!(f, "{}", reason),
+        }
+    }
+}
+
+/* Single characters */
 
 fn parse_piece(c: char) -> Option<chess::Piece> {
     use chess::Piece::*;
@@ -273,31 +510,6 @@ fn parse_piece(c: char) -> Option<chess::Piece> {
 
 }
 
-/*impl str::FromStr for PGNPiece {
-    type Err = ParseErr;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 1 {
-            return Err(format!("\"{}\" is too long to be a piece", s));
-        }
-        else {
-            let first_char = s.chars().next().unwrap();
-            parse_piece(first_char)
-                .ok_or(format!("\'{}\' does not represent any chess piece", first_char))
-        }
-    }
-}*/
-impl ParseChar for PGNPiece {
-    fn parse_char(c: char) -> ParseRes<Self> {
-        match parse_piece(c) {
-            Some(p) => Ok(PGNPiece(p)),
-            None    => Err(format!("\'{}\' does not represent any chess piece", c))
-        }
-    }
-}
-
-/* Parsing Square */
-
 fn parse_file(c: char) -> Option<chess::File> {
     use chess::File;
     match c {
@@ -313,15 +525,6 @@ fn parse_file(c: char) -> Option<chess::File> {
     }
 }
 
-impl ParseChar for PGNFile {
-    fn parse_char(c: char) -> ParseRes<Self> {
-        match parse_file(c) {
-            Some(f) => Ok(PGNFile(f)),
-            None    => Err(format!("\'{}\' does not represent any chess file", c))
-        }
-    }
-}
-
 fn parse_rank(c: char) -> Option<chess::Rank> {
     use chess::Rank;
     match c {
@@ -337,153 +540,815 @@ fn parse_rank(c: char) -> Option<chess::Rank> {
     }
 }
 
-impl ParseChar for PGNRank {
-    fn parse_char(c: char) -> ParseRes<Self> {
-        match parse_rank(c) {
-            Some(r) => Ok(PGNRank(r)),
-            None    => Err(format!("\'{}\' does not represent any chess rank", c))
-        }
+/* Moves resolved against a board */
+
+/* Accepts both SAN ("Nf3", "exd5", "O-O", "e8=Q+") and coordinate
+ * notation ("g1f3", "e7e8q"). The move must be legal on the given board.
+ */
+pub fn parse_move(board: &Board, s: &str) -> ParseRes<ChessMove> {
+    let legal_moves: Vec<ChessMove> = MoveGen::new_legal(board).collect();
+
+    /* Coordinate notation */
+    if let Some(mv) = legal_moves.iter().find(|mv| format!("{}", mv) == s) {
+        return Ok(*mv);
     }
-}
 
-impl Parse for PGNSquare {
-    fn parse<'a>(s: &'a str) -> ParseRes<ParsedPrefix<'a, Self>> {
-        let mut parser = Parser::new(s);
-        let file = parser.parse::<PGNFile>()?;
-        let rank = parser.parse::<PGNRank>()?;
-        let square = Square::make_square(rank.into(), file.into());
-        parser.finalize_prefix(PGNSquare(square))
+    /* Check, mate and annotation markers don't help to find the move */
+    let san = s.trim_end_matches(['+', '#', '!', '?']);
+
+    let candidates: Vec<ChessMove> = match san {
+        "O-O" | "0-0" =>
+            legal_moves.into_iter()
+                       .filter(|mv| is_castle(board, *mv) && mv.get_dest().get_file() == File::G)
+                       .collect(),
+
+        "O-O-O" | "0-0-0" =>
+            legal_moves.into_iter()
+                       .filter(|mv| is_castle(board, *mv) && mv.get_dest().get_file() == File::C)
+                       .collect(),
+
+        _ => {
+            let pattern = parse_san_pattern(san)?;
+            legal_moves.into_iter()
+                       .filter(|mv| pattern.matches(board, *mv))
+                       .collect()
+        }
+    };
+
+    match candidates.len() {
+        1 => Ok(candidates[0]),
+        0 => Err(ParseErr::new(ParseErrReason::IllegalMove, s)),
+        _ => Err(ParseErr::new(ParseErrReason::AmbiguousMove(candidates), s)),
     }
 }
 
-/* Complete move parsing */
+/* Castling is the only way for a king to move by two files */
+fn is_castle(board: &Board, mv: ChessMove) -> bool {
+    let source_file = mv.get_source().get_file().to_index() as i8;
+    let dest_file = mv.get_dest().get_file().to_index() as i8;
 
-struct PGNMove {
-    piece:     chess::Piece,
-    base_move: chess::ChessMove,
+    board.piece_on(mv.get_source()) == Some(Piece::King)
+        && (dest_file - source_file).abs() == 2
 }
 
-impl str::FromStr for PGNMove {
-    type Err = ParseErr;
+/* What a SAN move tells about the move it stands for */
+struct SanPattern {
+    piece:       Piece,
+    source_file: Option<chess::File>,
+    source_rank: Option<chess::Rank>,
+    dest:        Square,
+    promotion:   Option<Piece>,
+}
 
-    fn from_str(s: &str) -> ParseRes<Self> {
-        parse_castle_move(s)
-            .or_else(|_| parse_regular_move_or_promote(s))
+impl SanPattern {
+    fn matches(&self, board: &Board, mv: ChessMove) -> bool {
+        let source = mv.get_source();
+
+        board.piece_on(source) == Some(self.piece)
+            && mv.get_dest() == self.dest
+            && mv.get_promotion() == self.promotion
+            && self.source_file.is_none_or(|file| source.get_file() == file)
+            && self.source_rank.is_none_or(|rank| source.get_rank() == rank)
     }
 }
 
-fn parse_regular_move_prefix(s: &str) -> ParseRes<ParsedPrefix<PGNMove>> {
-    let mut parser  = Parser::new(s);
-    let piece       = parser.parse::<PGNPiece>()?;
-    let source      = parser.parse::<PGNSquare>()?;
-    let dest        = parser.parse::<PGNSquare>()?;
+fn parse_san_pattern(san: &str) -> ParseRes<SanPattern> {
+    let invalid = || ParseErr::syntax(san, "is not a valid move");
+    let mut chars: Vec<char> = san.chars().collect();
+
+    /* Promotion: "e8=Q" or "e8Q" */
+    let mut promotion = None;
+    if let Some(last_char) = chars.last().copied() {
+        if last_char.is_ascii_uppercase() {
+            promotion = Some(parse_piece(last_char).ok_or_else(invalid)?);
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+    }
 
-    let mv = PGNMove {
-        piece:     piece.into(),
-        base_move: chess::ChessMove::new(
-            source.into(),
-            dest.into(),
-            None
-        )
+    /* Destination square */
+    let (rank_char, file_char) = match (chars.pop(), chars.pop()) {
+        (Some(rank_char), Some(file_char)) => (rank_char, file_char),
+        _                                  => return Err(invalid()),
+    };
+    let dest = Square::make_square(parse_rank(rank_char).ok_or_else(invalid)?,
+                                   parse_file(file_char).ok_or_else(invalid)?);
+
+    /* Moved piece: pawns have no letter */
+    let piece = match chars.first() {
+        Some(c) if c.is_ascii_uppercase() => {
+            let piece = parse_piece(*c).ok_or_else(invalid)?;
+            chars.remove(0);
+            piece
+        }
+        _ => Piece::Pawn
     };
 
-    parser.finalize_prefix(mv)
+    /* Whatever remains is the capture marker and disambiguation */
+    let mut source_file = None;
+    let mut source_rank = None;
+    for c in chars.into_iter().filter(|c| *c != 'x') {
+        if let Some(file) = parse_file(c) {
+            source_file = Some(file);
+        }
+        else if let Some(rank) = parse_rank(c) {
+            source_rank = Some(rank);
+        }
+        else {
+            return Err(invalid());
+        }
+    }
+
+    Ok(SanPattern {
+        piece,
+        source_file,
+        source_rank,
+        dest,
+        promotion
+    })
 }
 
-fn parse_regular_move(s: &str) -> ParseRes<PGNMove> {
-    parse_regular_move_prefix(s)?
-        .as_full_parse()
+/* Complete game parsing */
+
+/* Tag pairs, e.g. [WhiteSeed "42"], in the order of the pgn */
+fn read_tags(input: &str) -> Vec<(String, String)> {
+    input.lines()
+         .filter_map(|line| line.trim().strip_prefix('[')?.strip_suffix(']'))
+         .filter_map(|tag| {
+             let (name, value) = tag.split_once(char::is_whitespace)?;
+             let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+             Some((String::from(name), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+         })
+         .collect()
 }
 
-fn parse_regular_move_or_promote(s: &str) -> ParseRes<PGNMove> {
-    let prefix = parse_regular_move_prefix(s)?;
-    let rem_str = prefix.rem_str;
-    let mut regular_move = prefix.value;
+/* Where the tag is in the pgn, for errors */
+fn tag_pos(input: &str, name: &str) -> Option<TextPos> {
+    let prefix = format!("[{} ", name);
+    input.lines()
+         .position(|line| line.trim_start().starts_with(&prefix))
+         .map(|line_idx| TextPos { line: line_idx + 1, column: 1 })
+}
 
-    if rem_str.is_empty() {
-        Ok(regular_move)
+/* The starting position and its move number, from the FEN tag if any */
+fn start_position(input: &str, tags: &[(String, String)]) -> ParseRes<(Board, u32)> {
+    match tags.iter().find(|(name, _)| name == "FEN") {
+        Some((_, fen)) => {
+            let board = Board::from_str(fen)
+                            .map_err(|err| {
+                                let err = ParseErr::syntax(fen, &format!("is not a valid FEN: {}", err));
+                                match tag_pos(input, "FEN") {
+                                    Some(pos) => err.at(pos),
+                                    None      => err,
+                                }
+                            })?;
+            let turn = fen.split_whitespace()
+                          .nth(5)
+                          .and_then(|turn| turn.parse::<u32>().ok())
+                          .unwrap_or(1);
+            Ok((board, turn))
+        }
+        None => Ok((Board::default(), 1)),
     }
-    else {
-        // this should be a promotion
-        if rem_str.chars().next().unwrap() != '=' {
-            return Err(format!("Invalid promote format: '{}'", s));
+}
+
+/* "12." or "12..." in front of a move, possibly glued to it as in "12.e4".
+ * Returns the move number, whether it is black's (three dots), and the move.
+ */
+fn split_move_number(token: &str) -> ParseRes<(Option<(u32, bool)>, &str)> {
+    let dot_idx = match token.find('.') {
+        Some(0)       => return Ok((None, token.trim_start_matches('.'))),  /* "12. ... Nf6" */
+        Some(dot_idx) => dot_idx,
+        None          => return Ok((None, token)),
+    };
+
+    let number = token[..dot_idx].parse::<u32>()
+                                 .map_err(|_| ParseErr::syntax(token, "is not a valid move number"))?;
+    let dots_and_move = &token[dot_idx..];
+    let mv_str = dots_and_move.trim_start_matches('.');
+    let is_black = dots_and_move.len() - mv_str.len() == 3;
+    Ok((Some((number, is_black)), mv_str))
+}
+
+/* The tags that the game tells by itself are left out */
+fn read_metadata(tags: &[Tag]) -> play::GameMetadata {
+    let mut metadata = play::GameMetadata::default();
+    for (name, value) in tags.iter().cloned() {
+        match name.as_str() {
+            "Event" => metadata.event = value,
+            "Site"  => metadata.site = value,
+            "Date"  => metadata.date = value,
+            "Round" => metadata.round = value,
+            "White" => metadata.white = value,
+            "Black" => metadata.black = value,
+            "Result" | "Termination" | "SetUp" | "FEN" => { }
+            _       => metadata.tags.push((name, value)),
         }
+    }
+    metadata
+}
 
-        let promote_piece = PGNPiece::parse(&rem_str[1..])?.as_full_parse()?;
-        let base_move = regular_move.base_move;
-        let promote_move = chess::ChessMove::new(
-            base_move.get_source(),
-            base_move.get_dest(),
-            Some(promote_piece.into())
-        );
+/* How the game ended, when the final position doesn't tell. The
+ * Termination tag tells adjudications and time forfeits apart from
+ * resignations and agreed draws.
+ */
+fn read_termination(game: &play::Game, result: &str, termination: Option<&str>) -> Option<play::Termination> {
+    use play::Termination::*;
+    if game.termination().is_some() {
+        return None;
+    }
 
-        regular_move.base_move = promote_move;
-        Ok(regular_move)
+    match (result, termination) {
+        ("1-0", Some("time forfeit"))     => Some(TimeForfeit(Color::Black)),
+        ("0-1", Some("time forfeit"))     => Some(TimeForfeit(Color::White)),
+        ("1-0", Some("adjudication"))     => Some(Adjudication(Some(Color::White))),
+        ("0-1", Some("adjudication"))     => Some(Adjudication(Some(Color::Black))),
+        ("1/2-1/2", Some("adjudication")) => Some(Adjudication(None)),
+        ("1-0", _)                        => Some(Resignation(Color::Black)),
+        ("0-1", _)                        => Some(Resignation(Color::White)),
+        ("1/2-1/2", _)                    => Some(Agreement),
+        _                                 => None,  /* unfinished */
     }
 }
 
-fn parse_castle_move(s: &str) -> ParseRes<PGNMove> {
-    Err(String::from("Not yet implemented"))
+const RESULT_TOKENS: [&str; 4] = ["*", "1-0", "0-1", "1/2-1/2"];
+
+/* Move text tokenizer */
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Token<'a> {
+    Symbol(&'a str),   /* move numbers, moves and results */
+    Nag(u8),           /* "$14", or a move suffix such as "!?" */
+    Comment(&'a str),  /* "{...}", or from ';' to the end of the line */
+    VariationStart,
+    VariationEnd,
 }
 
-/* Complete game parsing */
+/* Move suffixes are short forms of the first NAGs */
+const SUFFIX_NAGS: [(&str, u8); 6] = [("!", 1), ("?", 2), ("!!", 3), ("??", 4), ("!?", 5), ("?!", 6)];
+
+/* Tag lines and '%' escape lines are skipped, tags are read on their own */
+struct Tokenizer<'a> {
+    input:         &'a str,
+    pos:           usize,    /* byte offset of the next token */
+    text_pos:      TextPos,  /* of the next token */
+    at_line_start: bool,     /* only whitespace before the next token on its line */
+}
 
-pub fn read_pgn(input: &str) -> ParseRes<play::Game> {
-    let mut game = play::Game::new();
-    let mut turn = 1;
+impl<'a> Tokenizer<'a> {
+    fn new(input: &'a str) -> Self {
+        Tokenizer {
+            input,
+            pos:           0,
+            text_pos:      TextPos { line: 1, column: 1 },
+            at_line_start: true,
+        }
+    }
 
-    let mut chunks = input.split_whitespace();
-    while let Some(turn_indicator) = chunks.next() {
-        /* Validate the turn_indicator */
-        let expected_turnind = format!("{}.", turn);
-        assert_eq!(turn_indicator, &expected_turnind);
-        turn += 1;
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
 
-        /* Extract white's move (mandatory) */
-        let white_str = chunks.next().ok_or("White's move is mandatory each turn")?;
-        let white_move = white_str.parse::<PGNMove>()?;
-        game.play_move(white_move.into());
+    /* Moves past the given number of bytes, and returns them */
+    fn advance(&mut self, len: usize) -> &'a str {
+        let taken = &self.rest()[..len];
+        for c in taken.chars() {
+            if c == '\n' {
+                self.text_pos.line += 1;
+                self.text_pos.column = 1;
+                self.at_line_start = true;
+            }
+            else {
+                self.text_pos.column += 1;
+                self.at_line_start &= c.is_whitespace();
+            }
+        }
+        self.pos += len;
+        taken
+    }
 
-        /* Extract black's move (optional) */
-        let black_str = match chunks.next() {
-            Some(s) => s,
-            None    => return Ok(game),  /* EOS */
-        };
-        let black_move = black_str.parse::<PGNMove>()?;
-        game.play_move(black_move.into());
+    /* Moves past the end of the current line, and returns what it held */
+    fn take_line(&mut self) -> &'a str {
+        let line_len = self.rest().find('\n').unwrap_or(self.rest().len());
+        self.advance(line_len)
     }
 
-    Ok(game)
+    /* Moves past the chars that match, and returns them */
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &'a str {
+        let len = self.rest().find(|c| !pred(c)).unwrap_or(self.rest().len());
+        self.advance(len)
+    }
 }
 
-/* Conversions */
+fn is_symbol_char(c: char) -> bool {
+    !c.is_whitespace() && !"{}()[];$!?".contains(c)
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    /* With the position of the token */
+    type Item = ParseRes<(TextPos, Token<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.take_while(char::is_whitespace);
+            let start = self.text_pos;
+            let c = self.rest().chars().next()?;
+            let syntax_err = |token: &str, what: &str| ParseErr::syntax(token, what).at(start);
+
+            let token = match c {
+                '[' | '%' if self.at_line_start => { self.take_line(); continue; }
+
+                ';' => Token::Comment(self.take_line()[1..].trim()),
+
+                '{' => match self.rest().find('}') {
+                    Some(end) => {
+                        let comment = &self.advance(end + 1)[1..end];
+                        Token::Comment(comment.trim())
+                    }
+                    None => {
+                        let err = syntax_err("{", "opens a comment that is never closed");
+                        self.advance(self.rest().len());
+                        return Some(Err(err));
+                    }
+                },
+
+                '(' => { self.advance(1); Token::VariationStart }
+                ')' => { self.advance(1); Token::VariationEnd }
+
+                '$' => {
+                    self.advance(1);
+                    let digits = self.take_while(|c| c.is_ascii_digit());
+                    match digits.parse::<u8>() {
+                        Ok(nag) => Token::Nag(nag),
+                        Err(_)  => return Some(Err(syntax_err(&format!("${}", digits), "is not a valid NAG"))),
+                    }
+                }
+
+                '!' | '?' => {
+                    let suffix = self.take_while(|c| c == '!' || c == '?');
+                    match SUFFIX_NAGS.iter().find(|(rep, _)| *rep == suffix) {
+                        Some((_, nag)) => Token::Nag(*nag),
+                        None           => return Some(Err(syntax_err(suffix, "is not a valid move suffix"))),
+                    }
+                }
 
-impl convert::From<PGNRank> for chess::Rank {
-    fn from(pgn_rank: PGNRank) -> chess::Rank {
-        pgn_rank.0
+                _ => match self.take_while(is_symbol_char) {
+                    "" => {
+                        self.advance(c.len_utf8());
+                        return Some(Err(syntax_err(&c.to_string(), "is not expected in the move text")));
+                    }
+                    symbol => Token::Symbol(symbol),
+                },
+            };
+            return Some(Ok((start, token)));
+        }
     }
 }
 
-impl convert::From<PGNFile> for chess::File {
-    fn from(pgn_file: PGNFile) -> chess::File {
-        pgn_file.0
+/* Move tree */
+
+/* A move of the move text, with what the pgn tells about it */
+#[derive(Clone)]
+pub struct AnnotatedMove {
+    pub mv:         ChessMove,
+    pub nags:       Vec<u8>,
+    pub comments:   Vec<String>,  /* after the move */
+    pub variations: Vec<Line>,    /* played instead of the move, from the same position */
+}
+
+/* A sequence of moves, e.g. the main line of a game or one of its
+ * variations. The variations of its moves make the move text a tree.
+ */
+#[derive(Clone, Default)]
+pub struct Line {
+    pub comments: Vec<String>,  /* before the first move */
+    pub moves:    Vec<AnnotatedMove>,
+}
+
+/* A game with the whole content of its move text */
+pub struct AnnotatedGame {
+    pub game:       play::Game,  /* with the moves of the main line */
+    pub main_line:  Line,
+    pub first_turn: u32,         /* move number of the first move */
+}
+
+impl AnnotatedGame {
+    /* Move number of the ply, counted from 0 */
+    pub fn turn_of(&self, ply: usize) -> u32 {
+        let black_first = self.game.init_board.side_to_move() == Color::Black;
+        self.first_turn + ((ply + black_first as usize) / 2) as u32
     }
 }
 
-impl convert::From<PGNSquare> for chess::Square {
-    fn from(pgn_sq: PGNSquare) -> chess::Square {
-        pgn_sq.0
+/* Reads moves until the end of the line: the end of the variation when
+ * nested, i.e. opened at some position, the result or the end of the move
+ * text otherwise. The result is returned for the main line.
+ */
+fn parse_line<'a>(tokens: &mut Tokenizer<'a>, init_board: Board, init_turn: u32, init_ply: usize, opened_at: Option<TextPos>)
+    -> ParseRes<(Line, Option<&'a str>)>
+{
+    let mut line = Line::default();
+    let mut board = init_board;
+    let mut turn = init_turn;
+    let mut ply = init_ply;
+    /* Variations start from the position of the last move */
+    let mut last_move_start = None;
+
+    while let Some(token) = tokens.next() {
+        let (pos, token) = token?;
+
+        match token {
+            /* Some tools end variations with a result, which is not the one of the game */
+            Token::Symbol(result) if RESULT_TOKENS.contains(&result) =>
+                if opened_at.is_none() {
+                    return Ok((line, Some(result)));
+                },
+
+            /* "exd6 e.p.": the capture is found without it */
+            Token::Symbol("e.p.") => { }
+
+            Token::Symbol(symbol) => {
+                let to_play = board.side_to_move();
+                let (number, mv_str) = split_move_number(symbol).map_err(|err| err.at(pos).at_ply(ply))?;
+                if let Some((number, is_black)) = number {
+                    let expected = match to_play {
+                        Color::White => format!("where {}. was expected", turn),
+                        Color::Black => format!("where {}... was expected", turn),
+                    };
+                    if number != turn || (is_black && to_play == Color::White) {
+                        return Err(ParseErr::syntax(symbol, &format!("is found {}", expected)).at(pos).at_ply(ply));
+                    }
+                }
+                if mv_str.is_empty() {
+                    continue;  /* the move comes in the next token */
+                }
+
+                /* The move may be glued to its number */
+                let mv_pos = TextPos { column: pos.column + symbol.len() - mv_str.len(), ..pos };
+                let mv = parse_move(&board, mv_str).map_err(|err| err.at(mv_pos).at_ply(ply))?;
+                line.moves.push(AnnotatedMove {
+                    mv,
+                    nags:       Vec::new(),
+                    comments:   Vec::new(),
+                    variations: Vec::new(),
+                });
+                last_move_start = Some((board, turn, ply));
+                board = board.make_move_new(mv);
+                ply += 1;
+                if to_play == Color::Black {
+                    turn += 1;
+                }
+            }
+
+            Token::Nag(nag) => match line.moves.last_mut() {
+                Some(last_move) => last_move.nags.push(nag),
+                None            => return Err(ParseErr::syntax(&format!("${}", nag), "is found before any move").at(pos)),
+            },
+
+            Token::Comment(comment) => match line.moves.last_mut() {
+                Some(last_move) => last_move.comments.push(String::from(comment)),
+                None            => line.comments.push(String::from(comment)),
+            },
+
+            Token::VariationStart => match (line.moves.last_mut(), last_move_start) {
+                (Some(last_move), Some((start_board, start_turn, start_ply))) => {
+                    let (variation, _) = parse_line(tokens, start_board, start_turn, start_ply, Some(pos))?;
+                    last_move.variations.push(variation);
+                }
+                _ => return Err(ParseErr::syntax("(", "opens a variation before any move").at(pos)),
+            },
+
+            Token::VariationEnd if opened_at.is_some() => return Ok((line, None)),
+            Token::VariationEnd => return Err(ParseErr::syntax(")", "closes no variation").at(pos)),
+        }
+    }
+
+    match opened_at {
+        Some(pos) => Err(ParseErr::syntax("(", "opens a variation that is never closed").at(pos)),
+        None      => Ok((line, None)),
     }
 }
 
-impl convert::From<PGNPiece> for chess::Piece {
-    fn from(pgn_piece: PGNPiece) -> chess::Piece {
-        pgn_piece.0
+/* The move text ends at the result, which the Result tag may also give.
+ * Move numbers are optional, but must be right when given.
+ */
+pub fn read_pgn(input: &str) -> ParseRes<AnnotatedGame> {
+    let tags = read_tags(input);
+    let (init_board, first_turn) = start_position(input, &tags)?;
+    let (main_line, result_token) = parse_line(&mut Tokenizer::new(input), init_board, first_turn, 1, None)?;
+
+    let mut game = play::Game::starting_from(init_board);
+    for annotated in main_line.moves.iter() {
+        game.play_move(annotated.mv);
     }
+
+    let result = tags.iter()
+                     .find(|(name, _)| name == "Result")
+                     .map(|(_, value)| value.as_str())
+                     .or(result_token);
+    let termination = tags.iter()
+                          .find(|(name, _)| name == "Termination")
+                          .map(|(_, value)| value.as_str());
+    game.ended_by = read_termination(&game, result.unwrap_or("*"), termination);
+    game.metadata = read_metadata(&tags);
+
+    Ok(AnnotatedGame {
+        game,
+        main_line,
+        first_turn,
+    })
 }
 
-impl convert::From<PGNMove> for chess::ChessMove {
-    fn from(pgn_mv: PGNMove) -> chess::ChessMove {
-        pgn_mv.base_move
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_pos(input: &str) -> Option<TextPos> {
+        read_pgn(input).err().expect("the pgn should not be read").pos
+    }
+
+    #[test]
+    fn errors_tell_the_line_and_column_of_their_token() {
+        let input = "[Event \"test\"]\n\n1. e4 e5\n2. Nf3 Nf6 3. Kxe8 *\n";
+        assert_eq!(error_pos(input), Some(TextPos { line: 4, column: 15 }));
+    }
+
+    #[test]
+    fn columns_are_counted_in_chars() {
+        let input = "1. e4 {déjà vu} e5 2. Qxf7 *";
+        assert_eq!(error_pos(input), Some(TextPos { line: 1, column: 23 }));
+    }
+
+    #[test]
+    fn moves_glued_to_their_number_are_found_after_it() {
+        let input = "1. e4 e5\n  2.Ke3 *";
+        assert_eq!(error_pos(input), Some(TextPos { line: 2, column: 5 }));
+    }
+
+    #[test]
+    fn unclosed_variations_are_found_where_they_open() {
+        let input = "1. e4 e5\n2. Nf3 (2. Nc3 Nc6\n3. Bc4 *";
+        assert_eq!(error_pos(input), Some(TextPos { line: 2, column: 8 }));
+    }
+
+    #[test]
+    fn tag_lines_are_only_skipped_at_line_starts() {
+        let input = "1. e4 [e5] *";
+        assert_eq!(error_pos(input), Some(TextPos { line: 1, column: 7 }));
+    }
+
+    /* The SAN of the moves of the line, played from the board */
+    fn line_sans(board: Board, line: &Line) -> Vec<String> {
+        let mut board = board;
+        line.moves.iter()
+                  .map(|annotated| {
+                      let san = gen_move(&board, annotated.mv);
+                      board = board.make_move_new(annotated.mv);
+                      san
+                  })
+                  .collect()
+    }
+
+    fn after(sans: &[&str]) -> Board {
+        sans.iter().fold(Board::default(), |board, san| board.make_move_new(parse_move(&board, san).unwrap()))
+    }
+
+    fn main_line(input: &str) -> Line {
+        read_pgn(input).expect("the pgn should be read").main_line
+    }
+
+    #[test]
+    fn variations_are_nested_in_the_moves_they_replace() {
+        let line = main_line("1. e4 e5 (1... c5 2. Nf3 (2. Nc3 Nc6) 2... d6) 2. Nf3 *");
+        assert_eq!(line_sans(Board::default(), &line), ["e4", "e5", "Nf3"]);
+        assert!(line.moves[0].variations.is_empty());
+        assert!(line.moves[2].variations.is_empty());
+
+        assert_eq!(line.moves[1].variations.len(), 1);
+        let sicilian = &line.moves[1].variations[0];
+        assert_eq!(line_sans(after(&["e4"]), sicilian), ["c5", "Nf3", "d6"]);
+
+        assert_eq!(sicilian.moves[1].variations.len(), 1);
+        let closed = &sicilian.moves[1].variations[0];
+        assert_eq!(line_sans(after(&["e4", "c5"]), closed), ["Nc3", "Nc6"]);
+        assert!(closed.moves.iter().all(|annotated| annotated.variations.is_empty()));
+    }
+
+    #[test]
+    fn variations_can_follow_the_first_move() {
+        let line = main_line("1. e4 (1. d4 d5) (1. c4) 1... e5 2. Nf3 *");
+        assert_eq!(line_sans(Board::default(), &line), ["e4", "e5", "Nf3"]);
+
+        let variations = &line.moves[0].variations;
+        assert_eq!(variations.len(), 2);
+        assert_eq!(line_sans(Board::default(), &variations[0]), ["d4", "d5"]);
+        assert_eq!(line_sans(Board::default(), &variations[1]), ["c4"]);
+    }
+
+    #[test]
+    fn brace_and_rest_of_line_comments_go_to_the_move_before_them() {
+        let line = main_line("{Start} 1. e4 {best by test} e5 ; a classic {not a comment}\n2. Nf3 {a} {b} *");
+        assert_eq!(line.comments, ["Start"]);
+        assert_eq!(line.moves[0].comments, ["best by test"]);
+        assert_eq!(line.moves[1].comments, ["a classic {not a comment}"]);
+        assert_eq!(line.moves[2].comments, ["a", "b"]);
+    }
+
+    #[test]
+    fn comments_may_hold_what_looks_like_move_text() {
+        let line = main_line("1. e4 {1... c5 (2. Nf3) $1 ; !?} e5 *");
+        assert_eq!(line_sans(Board::default(), &line), ["e4", "e5"]);
+        assert_eq!(line.moves[0].comments, ["1... c5 (2. Nf3) $1 ; !?"]);
+        assert!(line.moves[0].nags.is_empty());
+        assert!(line.moves[0].variations.is_empty());
+    }
+
+    #[test]
+    fn nags_and_move_suffixes_go_to_the_move_before_them() {
+        let line = main_line("1. e4 $1 e5 $2 $13 2. Nf3!? Nc6?? 3. Bb5 *");
+        let nags: Vec<_> = line.moves.iter().map(|annotated| annotated.nags.clone()).collect();
+        assert_eq!(nags, [vec![1], vec![2, 13], vec![5], vec![4], vec![]]);
+    }
+
+    #[test]
+    fn annotations_before_any_move_are_errors() {
+        assert!(read_pgn("(1. d4) 1. e4 *").is_err());
+        assert!(read_pgn("$1 1. e4 *").is_err());
+        assert!(read_pgn("1. e4 e5) *").is_err());
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_str(fen).unwrap()
+    }
+
+    fn reason(board: &Board, san: &str) -> ParseErrReason {
+        parse_move(board, san).expect_err("the move should not be read").reason
+    }
+
+    #[test]
+    fn san_patterns_tell_the_piece_source_destination_and_promotion() {
+        let pattern = parse_san_pattern("Nbd7").unwrap();
+        assert_eq!(pattern.piece, Piece::Knight);
+        assert_eq!((pattern.source_file, pattern.source_rank), (Some(File::B), None));
+        assert_eq!(pattern.dest, Square::D7);
+        assert_eq!(pattern.promotion, None);
+
+        let pattern = parse_san_pattern("R1e2").unwrap();
+        assert_eq!(pattern.piece, Piece::Rook);
+        assert_eq!((pattern.source_file, pattern.source_rank), (None, Some(Rank::First)));
+
+        let pattern = parse_san_pattern("Qh4xe1").unwrap();
+        assert_eq!(pattern.piece, Piece::Queen);
+        assert_eq!((pattern.source_file, pattern.source_rank), (Some(File::H), Some(Rank::Fourth)));
+        assert_eq!(pattern.dest, Square::E1);
+
+        for san in ["exd8=Q", "exd8Q"] {
+            let pattern = parse_san_pattern(san).unwrap();
+            assert_eq!(pattern.piece, Piece::Pawn);
+            assert_eq!((pattern.source_file, pattern.source_rank), (Some(File::E), None));
+            assert_eq!(pattern.dest, Square::D8);
+            assert_eq!(pattern.promotion, Some(Piece::Queen));
+        }
+
+        for san in ["Zf3", "e9", "Nf", "N?f3", "e8=X"] {
+            assert!(parse_san_pattern(san).is_err(), "{} should not be read", san);
+        }
+    }
+
+    #[test]
+    fn moves_are_disambiguated_by_file_rank_or_square() {
+        /* Knights on b1 and f1 both reach d2 */
+        let knights = board("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1");
+        assert_eq!(parse_move(&knights, "Nbd2"), Ok(ChessMove::new(Square::B1, Square::D2, None)));
+        assert_eq!(parse_move(&knights, "Nfd2"), Ok(ChessMove::new(Square::F1, Square::D2, None)));
+
+        /* Rooks on a1 and a5 both reach a3 */
+        let rooks = board("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(parse_move(&rooks, "R1a3"), Ok(ChessMove::new(Square::A1, Square::A3, None)));
+        assert_eq!(parse_move(&rooks, "R5a3"), Ok(ChessMove::new(Square::A5, Square::A3, None)));
+
+        /* Queens on a1, a3 and c1 all reach b2: only the square tells them apart */
+        let queens = board("8/7k/8/8/8/Q7/8/Q1Q4K w - - 0 1");
+        assert_eq!(parse_move(&queens, "Qa1b2"), Ok(ChessMove::new(Square::A1, Square::B2, None)));
+        assert_eq!(parse_move(&queens, "Qa3xb2"), Ok(ChessMove::new(Square::A3, Square::B2, None)));
+        assert_eq!(parse_move(&queens, "Qcb2"), Ok(ChessMove::new(Square::C1, Square::B2, None)));
+    }
+
+    #[test]
+    fn moves_that_are_not_disambiguated_enough_are_ambiguous() {
+        let knights = board("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1");
+        assert_eq!(reason(&knights, "Nd2"),
+                   ParseErrReason::AmbiguousMove(vec![ChessMove::new(Square::B1, Square::D2, None),
+                                                      ChessMove::new(Square::F1, Square::D2, None)]));
+
+        let queens = board("8/7k/8/8/8/Q7/8/Q1Q4K w - - 0 1");
+        assert!(matches!(reason(&queens, "Qab2"), ParseErrReason::AmbiguousMove(candidates) if candidates.len() == 2));
+        assert!(matches!(reason(&queens, "Q1b2"), ParseErrReason::AmbiguousMove(candidates) if candidates.len() == 2));
+        assert!(matches!(reason(&queens, "Qb2"), ParseErrReason::AmbiguousMove(candidates) if candidates.len() == 3));
+    }
+
+    #[test]
+    fn promotions_need_their_piece() {
+        let pawn = board("1r5k/P7/8/8/8/8/8/K7 w - - 0 1");
+        assert_eq!(parse_move(&pawn, "a8=Q"), Ok(ChessMove::new(Square::A7, Square::A8, Some(Piece::Queen))));
+        assert_eq!(parse_move(&pawn, "a8N"), Ok(ChessMove::new(Square::A7, Square::A8, Some(Piece::Knight))));
+        assert_eq!(parse_move(&pawn, "axb8=R+"), Ok(ChessMove::new(Square::A7, Square::B8, Some(Piece::Rook))));
+        assert_eq!(reason(&pawn, "a8"), ParseErrReason::IllegalMove);
+        assert_eq!(reason(&pawn, "a8=K"), ParseErrReason::IllegalMove);
+    }
+
+    #[test]
+    fn castling_may_give_check() {
+        let white = board("5k2/8/8/8/8/8/8/4K2R w K - 0 1");
+        for san in ["O-O+", "0-0+", "O-O"] {
+            assert_eq!(parse_move(&white, san), Ok(ChessMove::new(Square::E1, Square::G1, None)));
+        }
+        assert_eq!(reason(&white, "O-O-O+"), ParseErrReason::IllegalMove);
+
+        let black = board("r3k3/8/8/8/8/8/8/3K4 b q - 0 1");
+        for san in ["O-O-O+", "0-0-0+"] {
+            assert_eq!(parse_move(&black, san), Ok(ChessMove::new(Square::E8, Square::C8, None)));
+        }
+        assert_eq!(reason(&black, "O-O"), ParseErrReason::IllegalMove);
+    }
+
+    #[test]
+    fn moves_that_no_piece_can_play_are_illegal() {
+        let start = Board::default();
+        assert_eq!(reason(&start, "Ke2"), ParseErrReason::IllegalMove);
+        assert_eq!(reason(&start, "e5"), ParseErrReason::IllegalMove);
+        assert_eq!(reason(&start, "Nbd2"), ParseErrReason::IllegalMove);
+        assert!(matches!(reason(&start, "Zz9"), ParseErrReason::BadSyntax(_)));
+    }
+
+    #[test]
+    fn every_legal_move_is_read_back_from_its_san() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1",          /* knights on the same rank */
+            "4k3/8/8/1N6/8/8/8/1N2K3 w - - 0 1",         /* knights on the same file */
+            "4k3/8/8/R6R/8/8/8/R3K3 w - - 0 1",          /* rooks on the same rank and file */
+            "8/7k/8/8/8/Q7/8/Q1Q4K w - - 0 1",           /* queens that only squares tell apart */
+            "1r5k/P7/8/8/8/8/6p1/K4R2 w - - 0 1",        /* promotions, with capture and check */
+            "1r5k/P7/8/8/8/8/6p1/K4R2 b - - 0 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",         /* en passant */
+            "r3k2r/8/8/8/3pP3/8/8/R3K2R b KQkq e3 0 1",  /* en passant and castling */
+        ];
+
+        for fen in fens {
+            let board = board(fen);
+            let mut sans: Vec<String> = Vec::new();
+            for mv in MoveGen::new_legal(&board) {
+                let san = gen_move(&board, mv);
+                assert_eq!(parse_move(&board, &san), Ok(mv), "{} in {}", san, fen);
+                sans.push(san);
+            }
+            let n_moves = sans.len();
+            sans.sort();
+            sans.dedup();
+            assert_eq!(sans.len(), n_moves, "the moves of {} should have distinct SANs", fen);
+        }
+    }
+
+    #[test]
+    fn san_disambiguates_only_as_much_as_needed() {
+        let knights = board("4k3/8/8/1N6/8/8/8/1N2KN2 w - - 0 1");
+        assert_eq!(gen_move(&knights, ChessMove::new(Square::B1, Square::C3, None)), "N1c3");
+        assert_eq!(gen_move(&knights, ChessMove::new(Square::B1, Square::D2, None)), "Nbd2");
+        assert_eq!(gen_move(&knights, ChessMove::new(Square::B1, Square::A3, None)), "N1a3");
+        assert_eq!(gen_move(&knights, ChessMove::new(Square::F1, Square::G3, None)), "Ng3");
+
+        let queens = board("8/7k/8/8/8/Q7/8/Q1Q4K w - - 0 1");
+        assert_eq!(gen_move(&queens, ChessMove::new(Square::A1, Square::B2, None)), "Qa1b2");
+
+        let en_passant = board("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        assert_eq!(gen_move(&en_passant, ChessMove::new(Square::E5, Square::D6, None)), "exd6");
+
+        let promotion = board("1r5k/P7/8/8/8/8/8/K7 w - - 0 1");
+        assert_eq!(gen_move(&promotion, ChessMove::new(Square::A7, Square::B8, Some(Piece::Queen))), "axb8=Q+");
+    }
+
+    #[test]
+    fn adjudications_tell_their_rule_in_a_final_comment() {
+        let mut game = play::Game::new();
+        game.play_move(parse_move(&game.final_board, "e4").unwrap());
+        game.ended_by = Some(play::Termination::Adjudication(Some(Color::Black)));
+        let pgn = game_pgn(&game);
+        assert!(pgn.contains("[Termination \"adjudication\"]"));
+        assert!(pgn.contains("1. e4 {Black wins by adjudication: White is too far behind in material} 0-1"), "{}", pgn);
+
+        /* The comment is read back with the last move */
+        let read = read_pgn(&pgn).expect("the pgn should be read");
+        assert_eq!(read.game.termination(), Some(play::Termination::Adjudication(Some(Color::Black))));
+        assert_eq!(read.main_line.moves[0].comments,
+                   ["Black wins by adjudication: White is too far behind in material"]);
+
+        game.ended_by = Some(play::Termination::Adjudication(None));
+        assert!(game_pgn(&game).contains("1. e4 {Draw by adjudication: the material is balanced} 1/2-1/2"));
+
+        game.ended_by = Some(play::Termination::Resignation(Color::White));
+        assert!(game_pgn(&game).contains("1. e4 0-1"));
     }
 }
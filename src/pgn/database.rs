@@ -0,0 +1,153 @@
+use super::{read_pgn, AnnotatedGame, ParseErr};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/* Reader for pgn files that hold many games, e.g. all the games of a
+ * tournament. The games are read one at a time, so that the file is never
+ * loaded as a whole.
+ *
+ * A game ends where the tags of the next one start. A malformed game is
+ * reported as an error, positioned in the whole file, and the reader goes
+ * on with the next game.
+ */
+
+pub struct GameReader<R: BufRead> {
+    lines:       io::Lines<R>,
+    line_number: usize,           /* of the last line read */
+    next_tag:    Option<String>,  /* first line of the next game, already read */
+    failed:      bool,            /* the input can't be read anymore */
+}
+
+/* Whether a {comment} is still open at the end of the line. Comments may
+ * span lines, and their lines may start like tags.
+ */
+fn ends_in_comment(line: &str, in_comment: bool) -> bool {
+    let mut in_comment = in_comment;
+    for c in line.chars() {
+        match c {
+            '{' if !in_comment => in_comment = true,
+            '}' if in_comment  => in_comment = false,
+            ';' if !in_comment => break,  /* the rest of the line is a comment */
+            _                  => { }
+        }
+    }
+    in_comment
+}
+
+impl GameReader<BufReader<File>> {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        File::open(path).map(|file| GameReader::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> GameReader<R> {
+    pub fn new(input: R) -> Self {
+        GameReader {
+            lines:       input.lines(),
+            line_number: 0,
+            next_tag:    None,
+            failed:      false,
+        }
+    }
+
+    /* The text of the next game, with the line it starts at */
+    fn next_game_text(&mut self) -> io::Result<Option<(usize, String)>> {
+        let mut text = String::new();
+        let mut start_line = None;
+        let mut in_move_text = false;
+        let mut in_comment = false;
+
+        if let Some(tag_line) = self.next_tag.take() {
+            start_line = Some(self.line_number);
+            text.push_str(&tag_line);
+            text.push('\n');
+        }
+
+        for line in self.lines.by_ref() {
+            let line = line?;
+            self.line_number += 1;
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() && start_line.is_none() {
+                continue;  /* the text starts at the first line of the game */
+            }
+            let is_tag = trimmed.starts_with('[') && !in_comment;
+            if is_tag && in_move_text {
+                /* the tags of the next game */
+                self.next_tag = Some(line);
+                break;
+            }
+            if !trimmed.is_empty() {
+                in_move_text |= !is_tag;
+                start_line = start_line.or(Some(self.line_number));
+            }
+            if !is_tag {
+                in_comment = ends_in_comment(&line, in_comment);
+            }
+            text.push_str(&line);
+            text.push('\n');
+        }
+
+        Ok(start_line.map(|start_line| (start_line, text)))
+    }
+}
+
+impl<R: BufRead> Iterator for GameReader<R> {
+    type Item = Result<AnnotatedGame, ParseErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        match self.next_game_text() {
+            Ok(Some((start_line, text))) =>
+                Some(read_pgn(&text).map_err(|err| err.shifted(start_line))),
+            Ok(None) => None,
+            Err(err) => {
+                self.failed = true;
+                Some(Err(ParseErr::unreadable(format!("Couldn't read the pgn after line {}: {}", self.line_number, err))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::TextPos;
+
+    /* Blank lines before the first game, and between the games */
+    const GAMES: &str = "\n\n[Event \"first\"]
+[Result \"*\"]
+
+1. d4 d5
+2. Ke3 *
+
+
+[Event \"second\"]
+
+1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0
+
+[Event \"third\"]
+
+1. c4 Nf6 2. Nc3 Kxe1 *
+";
+
+    #[test]
+    fn errors_are_positioned_in_the_whole_file_despite_blank_lines() {
+        let games: Vec<_> = GameReader::new(GAMES.as_bytes()).collect();
+        assert_eq!(games.len(), 3);
+
+        let first_err = games[0].as_ref().err().expect("the first game has an illegal move");
+        assert_eq!(first_err.pos, Some(TextPos { line: 7, column: 4 }));
+
+        let second = games[1].as_ref().expect("the second game should be read");
+        assert_eq!(second.game.metadata.event, "second");
+        assert_eq!(second.main_line.moves.len(), 7);
+
+        let third_err = games[2].as_ref().err().expect("the third game has an illegal move");
+        assert_eq!(third_err.pos, Some(TextPos { line: 16, column: 18 }));
+    }
+}
@@ -1,20 +1,206 @@
-use chess::{Board, BoardStatus, ChessMove, Color};
+use chess::{Board, BitBoard, BoardStatus, ChessMove, Color, Piece};
 use crate::utils;
+use crate::utils::dot;
 use crate::logging;
+use crate::eval;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::fmt;
+use std::time::{Duration, Instant};
 
 pub mod random;
 pub mod evaldriven;
 pub mod montecarlo;
 pub mod exhaustive;
 pub mod astar;
+pub mod human;
+pub mod external;
+pub mod registry;
+pub mod clock;
+pub mod timeman;
 mod searchtree;
 
+use clock::{Clock, ClockInfo, TimeControl};
+
 pub type Logger = dyn logging::Logger;
 
 /********** ChessPlayer *********/
 
 pub trait ChessPlayer {
-    fn pick_move(&mut self, board: &Board, logger: &mut Logger) -> ChessMove;
+    /* The history holds the positions of the game so far, the board last.
+     * Players that avoid repetitions search from it, others simply ignore it.
+     */
+    fn pick_move(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> ChessMove;
+
+    /* Same as 'pick_move', but also returns a summary of the search that
+     * led to the move, for players that have one.
+     */
+    fn pick_move_with_info(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> (ChessMove, Option<SearchInfo>) {
+        (self.pick_move(board, history, logger), None)
+    }
+
+    /* Same as 'pick_move', but also returns a report on the search that
+     * led to the move, for players that can explain their moves.
+     */
+    fn pick_move_with_explanation(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> (ChessMove, Option<Explanation>) {
+        (self.pick_move(board, history, logger), None)
+    }
+
+    /* Players that don't search under a time budget simply ignore this */
+    fn set_time_budget(&mut self, _time_budget: Duration) { }
+
+    /* The clocks before each move of a timed game, which replace the
+     * time budget. Players that don't manage their time simply ignore them.
+     */
+    fn set_clock(&mut self, _clock: &ClockInfo) { }
+
+    /* Players that use randomness draw it from this seed, so that their
+     * decisions can be replayed. Others simply ignore it.
+     */
+    fn set_seed(&mut self, _seed: Seed) { }
+
+    /* Players that think on the opponent's time start searching the given
+     * position, the one they expect after the opponent's reply, in the
+     * background. The search goes on until they are asked for a move,
+     * which confirms it if the game reached that position and discards it
+     * otherwise. The history must lead to the position. Others simply
+     * ignore this.
+     */
+    fn ponder(&mut self, _board: &Board, _history: &PositionHistory) { }
+
+    /* Whether 'ponder' does anything, e.g. to offer pondering to GUIs */
+    fn can_ponder(&self) -> bool {
+        false
+    }
+
+    /* Stops the search started by 'ponder' without moving on, e.g. when the
+     * opponent played another reply. Returns the best move of the pondered
+     * position, if the player was pondering.
+     */
+    fn stop_pondering(&mut self) -> Option<ChessMove> {
+        None
+    }
+
+    /* Engines always play a move, but humans may want to take
+     * moves back or to give up.
+     */
+    fn decide(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> Decision {
+        Decision::Play(self.pick_move(board, history, logger))
+    }
+
+    /* Same as 'decide', but also returns a summary of the search when the
+     * player plays a move it searched for.
+     */
+    fn decide_with_info(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> (Decision, Option<SearchInfo>) {
+        (self.decide(board, history, logger), None)
+    }
+}
+
+pub enum Decision {
+    Play(ChessMove),
+    Undo,    /* take back our last move (and the opponent's reply) */
+    Resign,
+}
+
+impl ChessPlayer for Box<dyn ChessPlayer> {
+    fn pick_move(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> ChessMove {
+        (**self).pick_move(board, history, logger)
+    }
+
+    fn decide(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> Decision {
+        (**self).decide(board, history, logger)
+    }
+
+    fn decide_with_info(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> (Decision, Option<SearchInfo>) {
+        (**self).decide_with_info(board, history, logger)
+    }
+
+    fn pick_move_with_info(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> (ChessMove, Option<SearchInfo>) {
+        (**self).pick_move_with_info(board, history, logger)
+    }
+
+    fn pick_move_with_explanation(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> (ChessMove, Option<Explanation>) {
+        (**self).pick_move_with_explanation(board, history, logger)
+    }
+
+    fn set_time_budget(&mut self, time_budget: Duration) {
+        (**self).set_time_budget(time_budget)
+    }
+
+    fn set_clock(&mut self, clock: &ClockInfo) {
+        (**self).set_clock(clock)
+    }
+
+    fn set_seed(&mut self, seed: Seed) {
+        (**self).set_seed(seed)
+    }
+
+    fn ponder(&mut self, board: &Board, history: &PositionHistory) {
+        (**self).ponder(board, history)
+    }
+
+    fn can_ponder(&self) -> bool {
+        (**self).can_ponder()
+    }
+
+    fn stop_pondering(&mut self) -> Option<ChessMove> {
+        (**self).stop_pondering()
+    }
+}
+
+/********** SearchInfo **********/
+
+/* What a player found while searching for its last move.
+ * The score is given from the point of view of the player to move.
+ */
+pub struct SearchInfo {
+    pub score:     Option<eval::Score>,
+    pub best_line: Vec<ChessMove>,
+    pub nodes:     u32,
+    pub depth:     u16,
+    pub duration:  Duration,
+}
+
+/********** Explanation **********/
+
+/* A move considered at the root of the search */
+pub struct Candidate {
+    pub mv:        ChessMove,
+    pub score:     Option<eval::Score>,  /* for the player to move */
+    pub rating:    String,               /* how the player rates the move, in its own terms */
+    pub best_line: Vec<ChessMove>,       /* starts with the move */
+    pub nodes:     Option<u32>,          /* size of the tree searched after the move */
+}
+
+/* Why a player picked a move */
+pub struct Explanation {
+    pub info:       SearchInfo,
+    pub candidates: Vec<Candidate>,     /* best first */
+    pub dot_graph:  Option<dot::Graph>, /* the search tree, for players that have one */
+}
+
+/********** Seeds **********/
+
+pub type Seed = u64;
+
+/* Random number generator for a single decision: it only depends on the
+ * seed and the position, so that any decision can be replayed on its own.
+ */
+pub fn decision_rng(seed: Seed, board: &Board) -> StdRng {
+    StdRng::seed_from_u64(seed ^ board.get_hash())
+}
+
+/* A different seed for each index, e.g. for each game of a match */
+pub fn derive_seed(seed: Seed, idx: u64) -> Seed {
+    /* splitmix64 */
+    let mut z = seed.wrapping_add(idx.wrapping_add(1).wrapping_mul(0x9E3779B97F4A7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+pub fn random_seed() -> Seed {
+    rand::thread_rng().gen()
 }
 
 /********** DebugPlayer **********/
@@ -22,79 +208,600 @@ pub trait ChessPlayer {
 pub trait DebugPlayer {
     type DebugData;
 
-    fn compute_move(&mut self, board: &Board, logger: &mut Logger) -> Self::DebugData;
+    fn compute_move(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> Self::DebugData;
 
     fn best_move(&self, data: &Self::DebugData) -> ChessMove;
 
+    /* The best move is given so that the reported line starts with it */
+    fn search_info(&self, _data: &Self::DebugData, _best_move: ChessMove) -> Option<SearchInfo> {
+        None
+    }
+
+    fn explanation(&self, _data: &Self::DebugData, _best_move: ChessMove) -> Option<Explanation> {
+        None
+    }
+
+    fn set_time_budget(&mut self, _time_budget: Duration) { }
+
+    fn set_clock(&mut self, _clock: &ClockInfo) { }
+
+    fn set_seed(&mut self, _seed: Seed) { }
+
+    fn ponder(&mut self, _board: &Board, _history: &PositionHistory) { }
+
+    fn can_ponder(&self) -> bool {
+        false
+    }
+
+    fn stop_pondering(&mut self) -> Option<ChessMove> {
+        None
+    }
 }
 
 impl<T: DebugPlayer> ChessPlayer for T {
-    fn pick_move(&mut self, board: &Board, logger: &mut Logger) -> ChessMove {
-        let data = self.compute_move(board, logger);
+    fn pick_move(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> ChessMove {
+        let data = self.compute_move(board, history, logger);
         self.best_move(&data)
     }
+
+    fn pick_move_with_info(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> (ChessMove, Option<SearchInfo>) {
+        let data = self.compute_move(board, history, logger);
+        let best_move = self.best_move(&data);
+        (best_move, self.search_info(&data, best_move))
+    }
+
+    fn pick_move_with_explanation(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> (ChessMove, Option<Explanation>) {
+        let data = self.compute_move(board, history, logger);
+        let best_move = self.best_move(&data);
+        (best_move, self.explanation(&data, best_move))
+    }
+
+    fn decide_with_info(&mut self, board: &Board, history: &PositionHistory, logger: &mut Logger) -> (Decision, Option<SearchInfo>) {
+        let (best_move, info) = self.pick_move_with_info(board, history, logger);
+        (Decision::Play(best_move), info)
+    }
+
+    fn set_time_budget(&mut self, time_budget: Duration) {
+        DebugPlayer::set_time_budget(self, time_budget)
+    }
+
+    fn set_clock(&mut self, clock: &ClockInfo) {
+        DebugPlayer::set_clock(self, clock)
+    }
+
+    fn set_seed(&mut self, seed: Seed) {
+        DebugPlayer::set_seed(self, seed)
+    }
+
+    fn ponder(&mut self, board: &Board, history: &PositionHistory) {
+        DebugPlayer::ponder(self, board, history)
+    }
+
+    fn can_ponder(&self) -> bool {
+        DebugPlayer::can_ponder(self)
+    }
+
+    fn stop_pondering(&mut self) -> Option<ChessMove> {
+        DebugPlayer::stop_pondering(self)
+    }
+}
+
+/********** Draw rules **********/
+
+/* Draws that the board alone doesn't tell, unlike stalemate */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DrawRule {
+    Repetition,            /* the same position for the third time */
+    FiftyMoves,            /* 50 moves each without a capture or a pawn move */
+    InsufficientMaterial,  /* no sequence of legal moves can lead to a mate */
+}
+
+impl fmt::Display for DrawRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DrawRule::Repetition           => write!(f, "threefold repetition"),
+            DrawRule::FiftyMoves           => write!(f, "fifty-move rule"),
+            DrawRule::InsufficientMaterial => write!(f, "insufficient material"),
+        }
+    }
+}
+
+const FIFTY_MOVES_PLIES: u16 = 100;
+
+/* The positions of a game, the current one last */
+#[derive(Clone, Default)]
+pub struct PositionHistory {
+    init_board: Board,
+    moves:      Vec<ChessMove>,  /* played from init_board */
+    hashes:     Vec<u64>,
+    clocks:     Vec<u16>,        /* plies since the last capture or pawn move, for each position */
+}
+
+impl PositionHistory {
+    pub fn new(init_board: &Board) -> Self {
+        PositionHistory {
+            init_board: *init_board,
+            moves:      Vec::new(),
+            hashes:     vec![init_board.get_hash()],
+            clocks:     vec![0],
+        }
+    }
+
+    /* The position the moves are played from, e.g. to send the game to an engine */
+    pub fn init_board(&self) -> &Board {
+        &self.init_board
+    }
+
+    pub fn moves(&self) -> &[ChessMove] {
+        &self.moves
+    }
+
+    /* The history up to the board, or only the board if it is not the
+     * current position (e.g. the history is of another game).
+     */
+    pub fn leading_to(&self, board: &Board) -> Self {
+        if self.hashes.last() == Some(&board.get_hash()) {
+            self.clone()
+        }
+        else {
+            Self::new(board)
+        }
+    }
+
+    /* The board is the position before the move */
+    pub fn push(&mut self, board: &Board, mv: ChessMove) {
+        let clock = self.clock_after(board, mv);
+        self.moves.push(mv);
+        self.hashes.push(board.make_move_new(mv).get_hash());
+        self.clocks.push(clock);
+    }
+
+    fn clock_after(&self, board: &Board, mv: ChessMove) -> u16 {
+        let is_capture = board.piece_on(mv.get_dest()).is_some();
+        let is_pawn_move = board.piece_on(mv.get_source()) == Some(Piece::Pawn);
+        if is_capture || is_pawn_move { 0 } else { self.halfmove_clock() + 1 }
+    }
+
+    pub fn pop(&mut self) {
+        self.moves.pop();
+        self.hashes.pop();
+        self.clocks.pop();
+    }
+
+    /* The hashes are the ones of the last positions, the current one last */
+    pub fn ends_with(&self, hashes: &[u64]) -> bool {
+        self.hashes.ends_with(hashes)
+    }
+
+    pub fn halfmove_clock(&self) -> u16 {
+        self.clocks.last().copied().unwrap_or(0)
+    }
+
+    /* Only the positions since the last capture or pawn move can be the same */
+    fn occurrences(&self) -> usize {
+        let current = match self.hashes.last() {
+            Some(hash) => *hash,
+            None       => return 0,
+        };
+        self.hashes.iter()
+                   .rev()
+                   .take(self.halfmove_clock() as usize + 1)
+                   .filter(|hash| **hash == current)
+                   .count()
+    }
+
+    /* The board is the current position, and its game is not over */
+    pub fn draw_rule(&self, board: &Board) -> Option<DrawRule> {
+        if self.occurrences() >= 3 {
+            Some(DrawRule::Repetition)
+        }
+        else if self.halfmove_clock() >= FIFTY_MOVES_PLIES {
+            Some(DrawRule::FiftyMoves)
+        }
+        else if has_insufficient_material(board) {
+            Some(DrawRule::InsufficientMaterial)
+        }
+        else {
+            None
+        }
+    }
+
+    /* Searches don't wait for the third time: if the position can be
+     * repeated once, it can be repeated twice.
+     */
+    pub fn is_search_draw(&self, board: &Board) -> bool {
+        self.occurrences() >= 2 || self.draw_rule(board).is_some()
+    }
+
+    /* Same as 'is_search_draw' for the position after the move, without
+     * pushing it: the board is the current position, before the move.
+     */
+    pub fn is_search_draw_after(&self, board: &Board, mv: ChessMove) -> bool {
+        let clock = self.clock_after(board, mv);
+        let new_board = board.make_move_new(mv);
+        let hash = new_board.get_hash();
+        let repeated = self.hashes.iter()
+                                  .rev()
+                                  .take(clock as usize)
+                                  .any(|prev_hash| *prev_hash == hash);
+        repeated || clock >= FIFTY_MOVES_PLIES || has_insufficient_material(&new_board)
+    }
+}
+
+/* Kings alone, or with a single minor piece, or with bishops all on the same color */
+pub fn has_insufficient_material(board: &Board) -> bool {
+    let heavy_or_pawns = *board.pieces(Piece::Pawn) | *board.pieces(Piece::Rook) | *board.pieces(Piece::Queen);
+    if heavy_or_pawns != chess::EMPTY {
+        return false;
+    }
+
+    let knights = *board.pieces(Piece::Knight);
+    let bishops = *board.pieces(Piece::Bishop);
+    if (knights | bishops).popcnt() <= 1 {
+        return true;
+    }
+
+    const DARK_SQUARES: BitBoard = BitBoard(0xAA55AA55AA55AA55);
+    knights == chess::EMPTY
+        && ((bishops & DARK_SQUARES) == chess::EMPTY || (bishops & !DARK_SQUARES) == chess::EMPTY)
+}
+
+/********** Termination **********/
+
+/* Why a game ended */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Draw(DrawRule),
+    Adjudication(Option<Color>),  /* the winner, None for a draw */
+    TimeForfeit(Color),           /* the player whose time ran out */
+    Resignation(Color),           /* the player who resigned */
+    Agreement,                    /* a draw agreed by the players, only found in imported games */
+    MoveLimit,                    /* interrupted: the game has no result */
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Termination::Checkmate       => write!(f, "checkmate"),
+            Termination::Stalemate       => write!(f, "stalemate"),
+            Termination::Draw(rule)      => write!(f, "{}", rule),
+            Termination::Adjudication(_) => write!(f, "adjudication"),
+            Termination::TimeForfeit(_)  => write!(f, "time forfeit"),
+            Termination::Resignation(_)  => write!(f, "resignation"),
+            Termination::Agreement       => write!(f, "agreement"),
+            Termination::MoveLimit       => write!(f, "move limit"),
+        }
+    }
+}
+
+/********** Arbiter **********/
+
+/* What is enforced on top of the rules of chess */
+#[derive(Clone, Copy, Default)]
+pub struct Arbiter {
+    pub adjudication: Adjudication,
+    pub time_control: Option<TimeControl>,  /* None for games without clocks */
+}
+
+/********** Adjudication **********/
+
+/* The arbiter may end games whose outcome is clear, from the material
+ * balance of the classic evaluation. Rules that are None are not applied.
+ */
+#[derive(Clone, Copy, Default)]
+pub struct Adjudication {
+    pub resign: Option<Threshold>,  /* a side is down by at least score */
+    pub draw:   Option<Threshold>,  /* the balance is within score */
+}
+
+#[derive(Clone, Copy)]
+pub struct Threshold {
+    pub score:   eval::Score,  /* in pawns */
+    pub plies:   u16,          /* in a row */
+    pub min_ply: usize,        /* the rule only ends games that are at least that long */
+}
+
+/* Balanced positions are the norm early on */
+pub const DEFAULT_DRAW_MIN_PLY: usize = 80;
+
+/* Counts the plies in a row that meet the rules */
+#[derive(Clone, Copy)]
+struct Adjudicator<'a> {
+    rules:          &'a Adjudication,
+    losing:         Option<Color>,
+    losing_plies:   u16,
+    balanced_plies: u16,
+}
+
+impl<'a> Adjudicator<'a> {
+    fn new(rules: &'a Adjudication) -> Self {
+        Adjudicator {
+            rules,
+            losing:         None,
+            losing_plies:   0,
+            balanced_plies: 0,
+        }
+    }
+
+    /* Called after each move of an ongoing game */
+    fn adjudicate(&mut self, game: &Game) -> Option<Termination> {
+        if self.rules.resign.is_none() && self.rules.draw.is_none() {
+            return None;
+        }
+
+        let balance = eval::classic_eval(&game.final_board, Color::White);
+
+        if let Some(resign) = self.rules.resign {
+            let losing = if balance <= -resign.score { Some(Color::White) }
+                         else if balance >= resign.score { Some(Color::Black) }
+                         else { None };
+            self.losing_plies = if losing.is_some() && losing == self.losing { self.losing_plies + 1 }
+                                else if losing.is_some() { 1 }
+                                else { 0 };
+            self.losing = losing;
+
+            let is_adjudicated = self.losing_plies >= resign.plies && game.moves.len() >= resign.min_ply;
+            if let Some(loser) = losing.filter(|_| is_adjudicated) {
+                return Some(Termination::Adjudication(Some(!loser)));
+            }
+        }
+
+        if let Some(draw) = self.rules.draw {
+            self.balanced_plies = if balance.abs() <= draw.score { self.balanced_plies + 1 } else { 0 };
+
+            if self.balanced_plies >= draw.plies && game.moves.len() >= draw.min_ply {
+                return Some(Termination::Adjudication(None));
+            }
+        }
+
+        None
+    }
 }
 
 /********** Game **********/
 
+/* What the pgn tells about a game, besides its moves and result.
+ * The Seven Tag Roster comes first, then the other tags in their order,
+ * e.g. the configuration of the players.
+ */
+#[derive(Clone)]
+pub struct GameMetadata {
+    pub event: String,
+    pub site:  String,
+    pub date:  String,  /* "YYYY.MM.DD", with question marks for the unknown parts */
+    pub round: String,
+    pub white: String,
+    pub black: String,
+    pub tags:  Vec<(String, String)>,
+}
+
+/* The pgn way of telling that something is unknown */
+impl Default for GameMetadata {
+    fn default() -> Self {
+        GameMetadata {
+            event: String::from("?"),
+            site:  String::from("?"),
+            date:  String::from("????.??.??"),
+            round: String::from("?"),
+            white: String::from("?"),
+            black: String::from("?"),
+            tags:  Vec::new(),
+        }
+    }
+}
+
+impl GameMetadata {
+    /* Value of one of the other tags */
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter()
+                 .find(|(tag_name, _)| tag_name == name)
+                 .map(|(_, value)| value.as_str())
+    }
+}
+
+/* What the game loop knows of a move besides the move itself */
+pub struct MoveAnnotation {
+    pub search:     Option<SearchInfo>,  /* of the player who moved */
+    pub clock_left: Option<Duration>,    /* on the clock of the player who moved, after the move */
+}
+
 pub struct Game {
     pub init_board:  Board,
     pub final_board: Board,
     pub moves:       Vec<ChessMove>,
+    pub history:     PositionHistory,      /* from init_board to final_board */
+    pub ended_by:    Option<Termination>,  /* the terminations that the board doesn't tell */
+    pub clock:       Option<Clock>,        /* None for games without clocks */
+    pub metadata:    GameMetadata,
+    /* Indexed like the moves, but only as long as the last annotated move,
+     * so that the games of rollouts don't pay for them.
+     */
+    pub annotations: Vec<Option<MoveAnnotation>>,
 }
 
 impl Game {
     pub fn new() -> Self {
+        Self::starting_from(Board::default())
+    }
+
+    pub fn starting_from(init_board: Board) -> Self {
+        Self::continuing(init_board, PositionHistory::new(&init_board))
+    }
+
+    /* A game from a position reached earlier, e.g. a rollout from the position of a search */
+    pub fn continuing(init_board: Board, history: PositionHistory) -> Self {
         Game {
-            init_board:  Board::default(),
-            final_board: Board::default(),
-            moves:       Vec::new()
+            init_board,
+            final_board: init_board,
+            moves:       Vec::new(),
+            history:     history.leading_to(&init_board),
+            ended_by:    None,
+            clock:       None,
+            metadata:    GameMetadata::default(),
+            annotations: Vec::new(),
         }
     }
 
-    #[allow(dead_code)]
+    /* Games stopped by the move limit are not over */
     pub fn is_over(&self) -> bool {
-        self.final_board.status() != BoardStatus::Ongoing
+        self.termination().is_some_and(|termination| termination != Termination::MoveLimit)
     }
 
-    pub fn winner(&self) -> Option<Color> {
+    pub fn termination(&self) -> Option<Termination> {
+        if self.ended_by.is_some() {
+            return self.ended_by;
+        }
+
         match self.final_board.status() {
-            BoardStatus::Checkmate => Some(!self.final_board.side_to_move()),
-            _                      => None
+            BoardStatus::Checkmate => Some(Termination::Checkmate),
+            BoardStatus::Stalemate => Some(Termination::Stalemate),
+            BoardStatus::Ongoing   => self.draw_rule().map(Termination::Draw),
         }
     }
 
-    pub fn result_for(&self, player: Color) -> Option<GameResult> {
-        match self.final_board.status() {
-            BoardStatus::Checkmate =>
-                if self.final_board.side_to_move() != player { Some(GameResult::Win) }
-                else { Some(GameResult::Lose) },
+    /* Checkmate and stalemate come first */
+    pub fn draw_rule(&self) -> Option<DrawRule> {
+        if self.final_board.status() != BoardStatus::Ongoing {
+            return None;
+        }
+        self.history.draw_rule(&self.final_board)
+    }
+
+    pub fn winner(&self) -> Option<Color> {
+        match self.termination()? {
+            Termination::Checkmate            => Some(!self.final_board.side_to_move()),
+            Termination::Adjudication(winner) => winner,
+            Termination::TimeForfeit(loser)   => Some(!loser),
+            Termination::Resignation(loser)   => Some(!loser),
+            _                                 => None
+        }
+    }
 
-            BoardStatus::Stalemate => Some(GameResult::Draw),
+    /* None if the game is not over */
+    pub fn result_for(&self, player: Color) -> Option<GameResult> {
+        if !self.is_over() {
+            return None;
+        }
 
-            BoardStatus::Ongoing => None
+        match self.winner() {
+            Some(winner) if winner == player => Some(GameResult::Win),
+            Some(_)                          => Some(GameResult::Lose),
+            None                             => Some(GameResult::Draw),
         }
     }
 
     pub fn play_move(&mut self, mv: ChessMove) {
+        self.history.push(&self.final_board, mv);
         self.final_board = self.final_board.make_move_new(mv);
         self.moves.push(mv);
     }
 
+    /* The annotation of the last move played */
+    pub fn annotate_last_move(&mut self, annotation: MoveAnnotation) {
+        if let Some(ply) = self.moves.len().checked_sub(1) {
+            self.annotations.resize_with(ply, || None);
+            self.annotations.push(Some(annotation));
+        }
+    }
+
+    pub fn annotation(&self, ply: usize) -> Option<&MoveAnnotation> {
+        self.annotations.get(ply).and_then(|annotation| annotation.as_ref())
+    }
+
+    pub fn undo_move(&mut self) -> Option<ChessMove> {
+        let undone = self.moves.pop();
+        if undone.is_some() {
+            self.history.pop();
+        }
+        self.annotations.truncate(self.moves.len());
+        /* Boards can't be unmade, replay the game instead */
+        self.final_board = self.moves.iter()
+                                     .fold(self.init_board, |board, mv| board.make_move_new(*mv));
+        undone
+    }
+
+    /* Plays at most max_moves more moves, then the game ends by the move limit */
     pub fn continue_playing<P1: ChessPlayer, P2: ChessPlayer>(
         &mut self,
         white:     &mut P1,
         black:     &mut P2,
         max_moves: MoveCount,
+        arbiter:   &Arbiter,
         logger:    &mut Logger)
     {
-        let mut game_played = play_n_moves(self.final_board, white, black, max_moves, logger);
+        let max_len = self.moves.len() + max_moves as usize;
+        let mut adjudicator = Adjudicator::new(&arbiter.adjudication);
+        /* The clock and the adjudicator before each move played here, so that undos rewind them too */
+        let mut snapshots = Vec::new();
+        if self.ended_by == Some(Termination::MoveLimit) {
+            self.ended_by = None;
+        }
+
+        while !self.is_over() && self.moves.len() < max_len {
+            let player = self.final_board.side_to_move();
+            let start_time = Instant::now();
+            let (decision, search) = match player {
+                Color::White => self.let_decide(white, logger),
+                Color::Black => self.let_decide(black, logger),
+            };
+
+            match decision {
+                Decision::Play(mv) => {
+                    snapshots.push((self.clock, adjudicator));
+
+                    /* Only moves are charged on the clock */
+                    let in_time = self.clock.as_mut()
+                                            .is_none_or(|clock| clock.punch(player, start_time.elapsed()));
+                    if !in_time {
+                        self.ended_by = Some(Termination::TimeForfeit(player));
+                        break;
+                    }
+
+                    self.play_move(mv);
+                    let clock_left = self.clock.as_ref().map(|clock| clock.time_left(player));
+                    if search.is_some() || clock_left.is_some() {
+                        self.annotate_last_move(MoveAnnotation { search, clock_left });
+                    }
+                    if !self.is_over() {
+                        self.ended_by = adjudicator.adjudicate(self);
+                    }
+                }
+
+                Decision::Undo =>
+                    /* Take back the opponent's reply and our own move */
+                    if snapshots.len() >= 2 {
+                        snapshots.pop();
+                        let (clock, prev_adjudicator) = snapshots.pop().unwrap();
+                        self.clock = clock;
+                        adjudicator = prev_adjudicator;
+                        self.undo_move();
+                        self.undo_move();
+                    }
+                    else {
+                        warn!(logger, "{:?} asked for an undo, but there is no move to take back", player);
+                    },
+
+                Decision::Resign => self.ended_by = Some(Termination::Resignation(player)),
+            }
+        }
 
-        self.final_board = game_played.final_board;
-        self.moves.append(&mut game_played.moves);
+        if !self.is_over() {
+            self.ended_by = Some(Termination::MoveLimit);
+        }
+    }
+
+    /* Tells the player what it needs to know of the game before it decides */
+    fn let_decide<P: ChessPlayer>(&self, player: &mut P, logger: &mut Logger) -> (Decision, Option<SearchInfo>) {
+        if let Some(clock) = &self.clock {
+            player.set_clock(&clock.info(self.final_board.side_to_move()));
+        }
+        player.decide_with_info(&self.final_board, &self.history, logger)
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum GameResult {
     Win,
     Draw,
@@ -102,12 +809,13 @@ pub enum GameResult {
 }
 
 pub fn play_game<P1: ChessPlayer, P2: ChessPlayer>(
-    white:  &mut P1,
-    black:  &mut P2,
-    logger: &mut Logger)
+    white:   &mut P1,
+    black:   &mut P2,
+    arbiter: &Arbiter,
+    logger:  &mut Logger)
     -> Game
 {
-    play_game_from(Board::default(), white, black, logger)
+    play_game_from(Board::default(), white, black, arbiter, logger)
 }
 
 const DEFAULT_MAX_MOVES: MoveCount = 200; /* 100 turns */
@@ -115,40 +823,49 @@ pub fn play_game_from<P1: ChessPlayer, P2: ChessPlayer>(
     start_pos: Board,
     white:     &mut P1,
     black:     &mut P2,
+    arbiter:   &Arbiter,
     logger:    &mut Logger)
     -> Game
 {
-    play_n_moves(start_pos, white, black, DEFAULT_MAX_MOVES, logger)
+    play_n_moves(start_pos, white, black, DEFAULT_MAX_MOVES, arbiter, logger)
 }
 
-type MoveCount = u8;
+/* The prelude, e.g. the moves of an opening, is part of the game: the
+ * players find its positions in the history.
+ */
+pub fn play_game_after<P1: ChessPlayer, P2: ChessPlayer>(
+    init_board: Board,
+    prelude:    &[ChessMove],
+    white:      &mut P1,
+    black:      &mut P2,
+    arbiter:    &Arbiter,
+    logger:     &mut Logger)
+    -> Game
+{
+    let mut game = Game::starting_from(init_board);
+    for mv in prelude.iter() {
+        game.play_move(*mv);
+    }
+    game.clock = arbiter.time_control.map(Clock::new);
+    game.continue_playing(white, black, DEFAULT_MAX_MOVES, arbiter, logger);
+    game
+}
+
+pub type MoveCount = u8;
 
 pub fn play_n_moves<P1: ChessPlayer, P2: ChessPlayer>(
     start_pos: Board,
     white:     &mut P1,
     black:     &mut P2,
     max_moves: MoveCount,
+    arbiter:   &Arbiter,
     logger:    &mut Logger)
     -> Game
 {
-    let mut board = start_pos.clone();
-    let mut move_list = Vec::new();
-    let max_moves = max_moves as usize;  /* convenience cast */
-
-    while board.status() == BoardStatus::Ongoing && move_list.len() < max_moves {
-        let mv = match board.side_to_move() {
-            Color::White => white.pick_move(&board, logger),
-            Color::Black => black.pick_move(&board, logger)
-        };
-        move_list.push(mv);
-        board = board.make_move_new(mv);
-    }
-
-    return Game {
-        init_board: start_pos,
-        final_board: board,
-        moves: move_list
-    };
+    let mut game = Game::starting_from(start_pos);
+    game.clock = arbiter.time_control.map(Clock::new);
+    game.continue_playing(white, black, max_moves, arbiter, logger);
+    game
 }
 
 pub fn replay_game(game: Game) -> impl Iterator<Item = (Board, ChessMove)> {
@@ -158,3 +875,108 @@ pub fn replay_game(game: Game) -> impl Iterator<Item = (Board, ChessMove)> {
         |board, mv| board.make_move_new(*mv)
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn;
+    use chess::MoveGen;
+    use std::collections::HashSet;
+    use std::str::FromStr;
+
+    fn game_from(fen: &str) -> Game {
+        Game::starting_from(Board::from_str(fen).unwrap())
+    }
+
+    fn play_sans(game: &mut Game, sans: &[&str]) {
+        for san in sans {
+            let mv = pgn::parse_move(&game.final_board, san).unwrap();
+            game.play_move(mv);
+        }
+    }
+
+    #[test]
+    fn kings_alone_are_a_draw() {
+        let game = game_from("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(game.termination(), Some(Termination::Draw(DrawRule::InsufficientMaterial)));
+        assert_eq!(game.result_for(Color::White), Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn bishops_on_the_same_color_are_a_draw_but_not_on_opposite_colors() {
+        /* c1 and f8 are both dark squares */
+        let same_colors = game_from("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1");
+        assert_eq!(same_colors.draw_rule(), Some(DrawRule::InsufficientMaterial));
+
+        /* c8 is a light square: a mate is possible, e.g. with the kings in a corner */
+        let opposite_colors = game_from("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1");
+        assert_eq!(opposite_colors.draw_rule(), None);
+        assert!(!opposite_colors.is_over());
+    }
+
+    #[test]
+    fn a_single_minor_piece_is_a_draw_but_not_with_a_pawn() {
+        assert!(has_insufficient_material(&Board::from_str("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1").unwrap()));
+        assert!(has_insufficient_material(&Board::from_str("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1").unwrap()));
+        assert!(!has_insufficient_material(&Board::from_str("4k3/8/8/8/8/8/P7/2B1K3 w - - 0 1").unwrap()));
+        assert!(!has_insufficient_material(&Board::from_str("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1").unwrap()));
+    }
+
+    #[test]
+    fn the_third_repetition_is_a_draw() {
+        let mut game = Game::new();
+        let shuffle = ["Nf3", "Nf6", "Ng1", "Ng8"];
+
+        play_sans(&mut game, &shuffle);
+        assert_eq!(game.draw_rule(), None);
+        /* searches don't wait for the third time */
+        assert!(game.history.is_search_draw(&game.final_board));
+
+        play_sans(&mut game, &shuffle[..3]);
+        assert_eq!(game.draw_rule(), None);
+        play_sans(&mut game, &shuffle[3..]);
+        assert_eq!(game.termination(), Some(Termination::Draw(DrawRule::Repetition)));
+    }
+
+    /* Quiet moves to new positions only, so that nothing repeats */
+    fn quiet_walk(board: &Board, n_plies: u16, seen: &mut HashSet<u64>) -> Option<Vec<ChessMove>> {
+        if n_plies == 0 {
+            return Some(Vec::new());
+        }
+        for mv in MoveGen::new_legal(board) {
+            let next = board.make_move_new(mv);
+            let is_quiet = board.piece_on(mv.get_dest()).is_none()
+                               && board.piece_on(mv.get_source()) != Some(Piece::Pawn);
+            if !is_quiet || next.status() != BoardStatus::Ongoing || !seen.insert(next.get_hash()) {
+                continue;
+            }
+            if let Some(mut walk) = quiet_walk(&next, n_plies - 1, seen) {
+                walk.insert(0, mv);
+                return Some(walk);
+            }
+            seen.remove(&next.get_hash());
+        }
+        None
+    }
+
+    #[test]
+    fn a_hundred_plies_without_capture_or_pawn_move_are_a_draw() {
+        let mut game = game_from("r3k3/7p/8/8/8/8/P7/4K2R w - - 0 1");
+        let walk = quiet_walk(&game.final_board, FIFTY_MOVES_PLIES, &mut HashSet::from([game.final_board.get_hash()]))
+                       .expect("there should be enough quiet moves");
+        for (ply, mv) in walk.into_iter().enumerate() {
+            assert_eq!(game.draw_rule(), None, "ply {}", ply);
+            game.play_move(mv);
+        }
+
+        assert_eq!(game.history.halfmove_clock(), FIFTY_MOVES_PLIES);
+        assert_eq!(game.termination(), Some(Termination::Draw(DrawRule::FiftyMoves)));
+
+        /* A pawn move starts counting again */
+        game.undo_move();
+        let pawn_move = if game.final_board.side_to_move() == Color::White { "a3" } else { "h6" };
+        play_sans(&mut game, &[pawn_move]);
+        assert_eq!(game.history.halfmove_clock(), 0);
+        assert_eq!(game.draw_rule(), None);
+    }
+}
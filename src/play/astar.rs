@@ -6,8 +6,13 @@ use chess::{Board, BoardStatus, ChessMove, Color, MoveGen};
 use crate::eval;
 use crate::eval::EvalFun;
 use super::searchtree;
-use super::{ChessPlayer, DebugPlayer};
-use std::time::{Duration, Instant};
+use super::{Candidate, DebugPlayer, Explanation, PositionHistory, SearchInfo, Seed};
+use super::clock::ClockInfo;
+use super::timeman::{Allotment, TimeManager};
+use std::time::Duration;
+use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use crate::utils::display;
 use crate::utils::display::JsonBuilder;
 use crate::logging;
@@ -20,8 +25,12 @@ use std::fmt;
 /* AStar ChessPlayer */
 
 pub struct AStar {
-    time_budget: Duration,
-    eval:        EvalFun,
+    time:      Allotment,
+    eval:      EvalFun,
+    seed:      Seed,
+    tree:      Option<SearchTree>,  /* the tree of the last search, reused by the next one */
+    pondering: bool,                /* ponder on its own after each move */
+    ponderer:  Option<Ponderer>,
 }
 
 /*impl ChessPlayer for AStar {
@@ -33,30 +42,94 @@ pub struct AStar {
 
 }*/
 
-pub struct OpaqueTree(SearchTree);
+/* The search tree itself is kept by the player */
+pub struct SearchStats {
+    duration: Duration,
+}
+
+impl AStar {
+    fn last_tree(&self) -> &SearchTree {
+        self.tree.as_ref().expect("No search was run")
+    }
+}
 
 impl DebugPlayer for AStar {
-    type DebugData = OpaqueTree;
+    type DebugData = SearchStats;
+
+    fn compute_move(&mut self, board: &Board, history: &PositionHistory, logger: &mut super::Logger) -> Self::DebugData {
+        /* The search extends its own copy of the history */
+        let history = history.leading_to(board);
+        /* Without a pondering hit, the tree of our last search may still help */
+        let pondered_tree = take_pondered_tree(&mut self.ponderer, board, &history, logger);
+        let reused_tree = pondered_tree.or_else(||
+            self.tree.take().and_then(|prev_tree| reusable_subtree(prev_tree, board, &history)));
+        let reused_nodes = reused_tree.as_ref().map_or(0, |tree| tree.count_nodes());
+
+        let (search_tree, duration) = astar_search(board, self.eval, &self.time, self.seed, history.clone(), reused_tree);
+
+        print_tree_statistics(&search_tree, self.eval, duration, reused_nodes, logger);
+        if self.pondering {
+            self.ponderer = ponder_expected_reply(&search_tree, self.eval, self.seed, &history);
+        }
+        self.tree = Some(search_tree);
 
-    fn compute_move(&mut self, board: &Board, logger: &mut super::Logger) -> Self::DebugData {
-        let search_tree = astar_search(board, self.eval, self.time_budget);
+        SearchStats {
+            duration,
+        }
+    }
+
+    fn best_move(&self, _stats: &SearchStats) -> ChessMove {
+        best_move(self.last_tree()).unwrap()
+    }
 
-        print_tree_statistics(&search_tree, self.eval, self.time_budget, logger);
+    fn search_info(&self, stats: &SearchStats, best_move: ChessMove) -> Option<SearchInfo> {
+        Some(search_info(self.last_tree(), best_move, stats.duration))
+    }
 
-        OpaqueTree(search_tree)
+    fn explanation(&self, stats: &SearchStats, best_move: ChessMove) -> Option<Explanation> {
+        Some(explanation(self.last_tree(), best_move, self.eval, stats.duration))
     }
 
-    fn best_move(&self, tree: &OpaqueTree) -> ChessMove {
-        best_move(&tree.0).unwrap()
+    fn set_time_budget(&mut self, time_budget: Duration) {
+        self.time = self.time.replaced_by(Allotment::PerMove(time_budget));
+    }
+
+    fn set_clock(&mut self, clock: &ClockInfo) {
+        self.time = self.time.replaced_by(Allotment::Clock(*clock));
+    }
+
+    fn set_seed(&mut self, seed: Seed) {
+        self.seed = seed;
+    }
+
+    fn ponder(&mut self, board: &Board, history: &PositionHistory) {
+        let history = history.leading_to(board);
+        let init_tree = self.tree.take().and_then(|prev_tree| reusable_subtree(prev_tree, board, &history));
+        self.ponderer = Some(Ponderer::start(*board, init_tree, self.eval, self.seed, history));
+    }
+
+    fn can_ponder(&self) -> bool {
+        true
+    }
+
+    fn stop_pondering(&mut self) -> Option<ChessMove> {
+        let tree = self.ponderer.take()?.finish();
+        let mv = best_move(&tree);
+        self.tree = Some(tree);
+        mv
     }
 }
 
 const DEFAULT_EVAL_FUN: EvalFun = eval::classic_eval;
 #[allow(dead_code)]
-pub fn astar_player(time_budget: Duration) -> AStar {
+pub fn astar_player(time: Allotment, pondering: bool, seed: Seed) -> AStar {
     AStar {
-        time_budget,
-        eval: DEFAULT_EVAL_FUN,
+        time,
+        eval:      DEFAULT_EVAL_FUN,
+        seed,
+        tree:      None,
+        pondering,
+        ponderer:  None,
     }
 }
 
@@ -126,6 +199,10 @@ impl BothScores {
             eval(board, Color::Black)
         )
     }
+
+    fn draw() -> Self {
+        Self::new(eval::DRAW_SCORE, eval::DRAW_SCORE)
+    }
 }
 
 impl fmt::Display for BothScores {
@@ -136,27 +213,100 @@ impl fmt::Display for BothScores {
 
 /********** AStar search code **********/
 
+/* Positions that repeat one of the history or of the line that leads to
+ * them are draws, as well as the ones that are draws by rule.
+ */
 fn astar_search(
     board:       &Board,
     eval_fun:    EvalFun,
-    time_budget: Duration)
-    -> SearchTree
+    time:        &Allotment,
+    seed:        Seed,
+    mut history: PositionHistory,
+    init_tree:   Option<SearchTree>)  /* to continue a previous search */
+    -> (SearchTree, Duration)
 {
-    let start_time = Instant::now();
-    let mut tree = init_root(board.clone(), eval_fun);
+    let mut time_manager = TimeManager::start(time, board);
+    let mut tree = match init_tree {
+        Some(tree) => tree,
+        None       => init_root(*board, eval_fun, seed, &mut history),
+    };
+    let player = board.side_to_move();
 
-    while start_time.elapsed() < time_budget {
-        descent(&mut tree, eval_fun);
+    while keep_searching(&mut time_manager, &tree, tree.moves.iter().map(|branch| branch.mv_data.get(player))) {
+        descent(&mut tree, eval_fun, seed, &mut history);
     }
 
-    return tree;
+    (tree, time_manager.elapsed())
 }
 
-fn init_root(init_board: Board, eval_fun: EvalFun) -> SearchTree {
-    new_node(init_board, eval_fun)
+/* The game may have gone through one of the lines of the previous
+ * search, within a couple of plies: the subtree of the new position is
+ * then searched further instead of starting again from scratch.
+ * The history must end with that line, so that the draws found in the
+ * subtree still hold.
+ */
+const REUSE_MAX_PLIES: usize = 2;
+
+fn reusable_subtree(prev_tree: SearchTree, board: &Board, history: &PositionHistory) -> Option<SearchTree> {
+    fn rec_find(node: SearchNode, board: &Board, history: &PositionHistory, mut line: Vec<u64>, plies_left: usize)
+        -> Option<SearchNode>
+    {
+        line.push(node.board.get_hash());
+        if node.board == *board {
+            return if history.ends_with(&line) { Some(node) } else { None };
+        }
+        if plies_left == 0 {
+            return None;
+        }
+
+        node.moves
+            .into_iter()
+            .filter_map(|branch| branch.child_node)
+            .find_map(|child| rec_find(child, board, history, line.clone(), plies_left - 1))
+    }
+
+    rec_find(prev_tree, board, history, Vec::new(), REUSE_MAX_PLIES)
+}
+
+/* A root move dominates when it is better than all the others by this many pawns */
+const DOMINANCE_MARGIN: i32 = 5;
+
+/* The scores of the root moves are given in the order of the moves, for
+ * the player to move. The best move is the first of the best ones, so
+ * that ties don't count as changes.
+ */
+fn keep_searching<I>(time_manager: &mut TimeManager, root: &SearchNode, scores: I) -> bool
+    where I: Iterator<Item = eval::Score>
+{
+    let mut best: Option<(usize, i32)> = None;
+    let mut runner_up: Option<i32> = None;
+    for (mv_idx, score) in scores.map(i32::from).enumerate() {
+        match best {
+            Some((_, best_score)) if score <= best_score => {
+                runner_up = cmp::max(runner_up, Some(score));
+            }
+            _ => {
+                runner_up = best.map(|(_, best_score)| best_score).or(runner_up);
+                best = Some((mv_idx, score));
+            }
+        }
+    }
+
+    match best {
+        Some((mv_idx, best_score)) => {
+            let dominant = runner_up.is_none_or(|score| best_score - score >= DOMINANCE_MARGIN);
+            time_manager.keep_searching(root.moves[mv_idx].mv, dominant)
+        }
+        None => false,  /* no move to search */
+    }
+}
+
+fn init_root(init_board: Board, eval_fun: EvalFun, seed: Seed, history: &mut PositionHistory) -> SearchTree {
+    new_node(init_board, eval_fun, seed, history)
 }
 
-fn descent(node: &mut SearchNode, eval_fun: EvalFun) -> BothScores {
+/* The history leads to the node's position */
+fn descent(node: &mut SearchNode, eval_fun: EvalFun, seed: Seed, history: &mut PositionHistory) -> BothScores {
     // FIXME shortcut this code if the game is over
     let curr_board = &node.board;
     if curr_board.status() != BoardStatus::Ongoing {
@@ -170,7 +320,9 @@ fn descent(node: &mut SearchNode, eval_fun: EvalFun) -> BothScores {
     let mv_idx     = best_entry.mv_idx;
     let branch     = &mut node.moves[mv_idx];
 
-    let new_scores = continue_descent(branch, curr_board, eval_fun);
+    history.push(curr_board, branch.mv);
+    let new_scores = continue_descent(branch, curr_board, eval_fun, seed, history);
+    history.pop();
 
     /* Update the heap */
     let eval_player = curr_board.side_to_move();
@@ -189,15 +341,27 @@ fn descent(node: &mut SearchNode, eval_fun: EvalFun) -> BothScores {
     return best_scores(node, eval_fun);
 }
 
-fn continue_descent(branch: &mut SearchMove, prev_board: &Board, eval_fun: EvalFun) -> BothScores {
+/* The history leads to the branch's position */
+fn continue_descent(
+    branch:     &mut SearchMove,
+    prev_board: &Board,
+    eval_fun:   EvalFun,
+    seed:       Seed,
+    history:    &mut PositionHistory)
+    -> BothScores
+{
     let new_scores = match branch.child_node.as_mut() {
-        Some(mut child) => {
+        Some(child) => {
             /* child node already expanded: recursively descent */
-            descent(&mut child, eval_fun)
+            descent(child, eval_fun, seed, history)
+        },
+        None if is_draw(&prev_board.make_move_new(branch.mv), history) => {
+            /* nothing to expand, the draw is final */
+            BothScores::draw()
         },
         None => {
             /* child not exanded yet: do it now and stop the recursion */
-            expand(branch, prev_board, eval_fun);
+            expand(branch, prev_board, eval_fun, seed, history);
             best_scores(branch.child_node.as_ref().unwrap(), eval_fun)
         }
     };
@@ -211,10 +375,10 @@ fn continue_descent(branch: &mut SearchMove, prev_board: &Board, eval_fun: EvalF
     return new_scores;
 }
 
-fn expand(branch: &mut SearchMove, prev_board: &Board, eval_fun: EvalFun) {
+fn expand(branch: &mut SearchMove, prev_board: &Board, eval_fun: EvalFun, seed: Seed, history: &mut PositionHistory) {
     let mv        = branch.mv;
     let new_board = prev_board.make_move_new(mv);
-    let new_child = new_node(new_board, eval_fun);
+    let new_child = new_node(new_board, eval_fun, seed, history);
 
 /*
 Simple board evaluation does not match branch data
@@ -228,19 +392,25 @@ Simple board evaluation does not match branch data
     branch.child_node = Some(new_child);
 }
 
-fn new_node(board: Board, eval_fun: EvalFun) -> SearchNode {
+/* The history leads to the node's position */
+fn new_node(board: Board, eval_fun: EvalFun, seed: Seed, history: &mut PositionHistory) -> SearchNode {
     /* Step 1: create the branches, with evaluation */
-    fn create_branches(board: &Board, eval_fun: EvalFun) -> Vec<SearchMove> {
+    fn create_branches(board: &Board, eval_fun: EvalFun, history: &mut PositionHistory) -> Vec<SearchMove> {
         let mut branches = Vec::new();
         for mv in MoveGen::new_legal(board) {
             /* Bug: Make sure to compute the scores wrt. the
              *      new board state.
              */
             let next_board = board.make_move_new(mv);
+            history.push(board, mv);
+            let mv_data = if is_draw(&next_board, history) { BothScores::draw() }
+                          else { BothScores::build_from(&next_board, eval_fun) };
+            history.pop();
+
             branches.push(
                 SearchMove {
                     mv,
-                    mv_data: BothScores::build_from(&next_board, eval_fun),
+                    mv_data,
                     child_node: None
                 }
             )
@@ -248,9 +418,12 @@ fn new_node(board: Board, eval_fun: EvalFun) -> SearchNode {
         return branches;
     }
 
-    /* Step 2: Build the initial heap state */
-    fn build_heap(moves: &[SearchMove], player: Color) -> NodeData {
-        let mut heap = MaxHeap::new();
+    /* Step 2: Build the initial heap state.
+     * Ties are broken the same way every time the same position is
+     * searched with the same seed.
+     */
+    fn build_heap(moves: &[SearchMove], player: Color, heap_seed: Seed) -> NodeData {
+        let mut heap = MaxHeap::new(heap_seed);
         for mv_idx in 0..moves.len() {
             let branch = &moves[mv_idx];
             let scores = branch.mv_data;
@@ -263,7 +436,7 @@ fn new_node(board: Board, eval_fun: EvalFun) -> SearchNode {
         return heap;
     }
 
-    let branches = create_branches(&board, eval_fun);
+    let branches = create_branches(&board, eval_fun, history);
     for b in branches.iter() {
         debug_assert!(branch_is_consistent(b, &board, eval_fun),
                       "Created an inconsistent branch in 'new_node()'");
@@ -273,13 +446,118 @@ fn new_node(board: Board, eval_fun: EvalFun) -> SearchNode {
 
     SearchNode {
         board,
-        node_data: build_heap(&branches, eval_player),
+        node_data: build_heap(&branches, eval_player, seed ^ board.get_hash()),
         moves: branches
     }
 }
 
+/********** Pondering **********/
+
+/* A search of the position expected after the opponent's reply, run in
+ * a background thread on the opponent's time. When our turn comes, its
+ * tree is reused if the opponent played that reply, and dropped otherwise.
+ */
+struct Ponderer {
+    stop:   Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<SearchTree>>,  /* taken when the search is finished */
+}
+
+/* The search stops by itself after that many descents, to bound its memory */
+const PONDER_MAX_DESCENTS: u32 = 200_000;
+
+impl Ponderer {
+    /* The history leads to the board */
+    fn start(
+        board:       Board,
+        init_tree:   Option<SearchTree>,
+        eval_fun:    EvalFun,
+        seed:        Seed,
+        mut history: PositionHistory)
+        -> Self
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            let mut tree = match init_tree {
+                Some(tree) => tree,
+                None       => init_root(board, eval_fun, seed, &mut history),
+            };
+            if board.status() == BoardStatus::Ongoing {
+                for _ in 0..PONDER_MAX_DESCENTS {
+                    if thread_stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    descent(&mut tree, eval_fun, seed, &mut history);
+                }
+            }
+            tree
+        });
+
+        Ponderer {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    fn finish(mut self) -> SearchTree {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle
+            .take()
+            .unwrap()
+            .join()
+            .expect("The pondering thread panicked")
+    }
+}
+
+/* A search that is not needed anymore stops at once */
+impl Drop for Ponderer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/* Ponders on the position after our best move and the best reply to it,
+ * starting from the subtree of the search. Nothing is expected when the
+ * search did not look at the replies.
+ */
+fn ponder_expected_reply(tree: &SearchTree, eval_fun: EvalFun, seed: Seed, history: &PositionHistory) -> Option<Ponderer> {
+    let our_branch = best_branch(tree)?;
+    let our_node = our_branch.child_node.as_ref()?;
+    let reply_branch = best_branch(our_node)?;
+
+    let mut history = history.clone();
+    history.push(&tree.board, our_branch.mv);
+    history.push(&our_node.board, reply_branch.mv);
+    let board = our_node.board.make_move_new(reply_branch.mv);
+
+    Some(Ponderer::start(board, reply_branch.child_node.clone(), eval_fun, seed, history))
+}
+
+/* The pondered tree, if the game reached the position it searched */
+fn take_pondered_tree(
+    ponderer: &mut Option<Ponderer>,
+    board:    &Board,
+    history:  &PositionHistory,
+    logger:   &mut super::Logger)
+    -> Option<SearchTree>
+{
+    let pondered_tree = ponderer.take()?.finish();
+    if pondered_tree.board != *board {
+        debug!(logger, "[AStar] Pondering miss: the opponent did not play the expected reply");
+        return None;
+    }
+
+    debug!(logger, "[AStar] Pondering hit: {} nodes searched on the opponent's time", pondered_tree.count_nodes());
+    reusable_subtree(pondered_tree, board, history)
+}
+
 /* Useful utilities */
 
+/* The history leads to the board. Games that are over are left to the evaluation. */
+fn is_draw(board: &Board, history: &PositionHistory) -> bool {
+    board.status() == BoardStatus::Ongoing && history.is_search_draw(board)
+}
+
 fn best_scores(node: &SearchNode, eval_fun: EvalFun) -> BothScores {
     match best_branch(node) {
         Some(branch) => branch.mv_data /*scores*/,
@@ -413,13 +691,14 @@ fn branch_is_consistent(branch: &SearchMove, prev_board: &Board, eval_fun: EvalF
     let branch_val = branch.mv_data.get(prev_player);
 
     /* 1. For non-expanded branches, the branch value must be the
-     *    value of the board.
+     *    value of the board, or a draw as the board may repeat the
+     *    line that leads to it (which is not known here).
      */
     if branch.child_node.is_none() {
         let next_board = prev_board.make_move_new(branch.mv);
         let next_board_val = eval_fun(&next_board, prev_player);
 
-        if branch_val != next_board_val {
+        if branch_val != next_board_val && branch.mv_data != BothScores::draw() {
             println!("Simple board evaluation does not match branch data");
             println!("(encountered in unexpanded branch)");
             println!("  value from branch data: {}", branch_val);
@@ -455,29 +734,83 @@ fn branch_is_consistent(branch: &SearchMove, prev_board: &Board, eval_fun: EvalF
 
 /********** Debugging at the end of the search **********/
 
-fn finalize(
-    final_tree: &SearchTree,
-    eval_fun:   EvalFun,
-    run_dur:    Duration,
-    logger:     &mut super::Logger)
-    -> ChessMove
+fn search_info(
+    tree:      &SearchTree,
+    best_move: ChessMove,
+    duration:  Duration)
+    -> SearchInfo
+{
+    let eval_player = tree.board.side_to_move();
+
+    /* The heap may hold several equivalent best moves, so make sure
+     * that the line starts with the one that was actually picked.
+     */
+    let best_branch = tree.moves.iter()
+                                .find(|b| b.mv == best_move)
+                                .unwrap();
+    let mut line = vec![best_move];
+    if let Some(child) = &best_branch.child_node {
+        line.append(&mut best_line(child));
+    }
+
+    SearchInfo {
+        score:     Some(best_branch.mv_data.get(eval_player)),
+        best_line: line,
+        nodes:     tree.count_nodes(),
+        depth:     tree.depth(),
+        duration
+    }
+}
+
+/* Every move at the root, with the line it leads to */
+fn explanation(
+    tree:      &SearchTree,
+    best_move: ChessMove,
+    eval_fun:  EvalFun,
+    duration:  Duration)
+    -> Explanation
 {
-    print_tree_statistics(&final_tree, eval_fun, run_dur, logger);
+    let eval_player = tree.board.side_to_move();
 
-    return best_move(&final_tree).unwrap();
+    let mut candidates: Vec<Candidate> = tree.moves.iter()
+        .map(|branch| {
+            let mut line = vec![branch.mv];
+            if let Some(child) = &branch.child_node {
+                line.append(&mut best_line(child));
+            }
+            Candidate {
+                mv:        branch.mv,
+                score:     Some(branch.mv_data.get(eval_player)),
+                rating:    format!("{}", branch.mv_data),
+                best_line: line,
+                nodes:     Some(branch.child_node.as_ref().map_or(0, |child| child.count_nodes())),
+            }
+        })
+        .collect();
+    /* Among equivalent moves, the one that was picked comes first */
+    candidates.sort_by_key(|candidate| (cmp::Reverse(candidate.score), candidate.mv != best_move));
+
+    Explanation {
+        info:      search_info(tree, best_move, duration),
+        candidates,
+        dot_graph: Some(build_dot_graph(tree, eval_fun)),
+    }
 }
 
+/* The reused nodes come from the previous search */
 fn print_tree_statistics(
-    tree:     &SearchTree,
-    eval_fun: EvalFun,
-    duration: Duration,
-    logger:   &mut super::Logger)
+    tree:         &SearchTree,
+    eval_fun:     EvalFun,
+    duration:     Duration,
+    reused_nodes: u32,
+    logger:       &mut super::Logger)
 {
     let node_count = tree.count_nodes();
     let ms = duration.as_millis();
 
     info!(logger, "[AStar statistics]");
     info!(logger, "  {} nodes searched in {}ms", node_count, ms);
+    info!(logger, "  {} nodes reused from the previous search", reused_nodes);
     info!(logger, "  average: {:.1} nodes per second", 1000. * (node_count as f32 / ms as f32));
     info!(logger, "  max tree depth: {}", tree.depth());
 
@@ -638,7 +971,6 @@ fn print_best_lines(tree: &SearchTree, eval_fun: EvalFun, logger: &mut super::Lo
             });
 }
 
-#[allow(dead_code)]
 fn best_line(tree: &SearchTree) -> Vec<ChessMove> {
     let mut curr_node = Some(tree);
     let mut line = Vec::new();
@@ -674,10 +1006,6 @@ fn best_line_full(tree: &SearchTree) -> Vec<Either<&SearchNode, &SearchMove>> {
 
 /* Generation of a dot graph */
 
-pub fn build_dot_graph_from(player: &AStar, tree: &OpaqueTree) -> dot::Graph {
-    build_dot_graph(&tree.0, player.eval)
-}
-
 fn build_dot_graph(tree: &SearchTree, eval_fun: EvalFun) -> dot::Graph {
     use dot::{NodeProp, EdgeProp, GraphProp};
 
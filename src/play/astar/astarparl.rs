@@ -1,9 +1,13 @@
 use chess;
 use chess::{Board, ChessMove};
+use super::Ponderer;
 use crate::eval;
 use crate::eval::EvalFun;
-use super::ChessPlayer;
-use std::time::{Duration, Instant};
+use super::DebugPlayer;
+use crate::play::{Explanation, PositionHistory, SearchInfo, Seed};
+use crate::play::clock::ClockInfo;
+use crate::play::timeman::{Allotment, TimeManager};
+use std::time::Duration;
 use std::thread;
 use std::sync::{Arc, Mutex};
 use crate::play;
@@ -16,32 +20,55 @@ use crate::play;
 pub type ThreadCount = u8;
 
 pub struct AStarPrl {
-    time_budget: Duration,
-    eval:        EvalFun,
-    n_threads:   ThreadCount
+    time:      Allotment,
+    eval:      EvalFun,
+    n_threads: ThreadCount,
+    seed:      Seed,
+    pondering: bool,  /* ponder on its own after each move, in a single thread */
+    ponderer:  Option<Ponderer>,
 }
 
-impl ChessPlayer for AStarPrl {
-    fn pick_move(&mut self, board: &Board, logger: &mut play::Logger) -> ChessMove {
-        let init_tree = init_root(board.clone(), self.eval);
-        let shared_tree = Arc::new(Mutex::new(init_tree));
+/* The tree is the same as for the sequential search */
+pub struct PrlTree {
+    tree:     SeqTree,
+    duration: Duration,
+}
+
+impl DebugPlayer for AStarPrl {
+    type DebugData = PrlTree;
+
+    fn compute_move(&mut self, board: &Board, history: &PositionHistory, logger: &mut play::Logger) -> PrlTree {
+        let history = history.leading_to(board);
+        let pondered_tree = super::take_pondered_tree(&mut self.ponderer, board, &history, logger);
+        let reused_nodes = pondered_tree.as_ref().map_or(0, |tree| tree.count_nodes());
+        let time_manager = TimeManager::start(&self.time, board);
+        let init_tree = match pondered_tree {
+            Some(tree) => tree,
+            None       => init_root(*board, self.eval, self.seed, &mut history.clone()),
+        };
+        let player = board.side_to_move();
+        let shared_root = SharedRoot {
+            scores: init_tree.moves.iter().map(|branch| branch.mv_data.get(player)).collect(),
+            tree:   init_tree,
+            time_manager,
+        };
+        let shared_tree = Arc::new(Mutex::new(shared_root));
         let eval_fun = self.eval;
+        let seed = self.seed;
         /*let shared_logger = Arc::new(Mutex::new(logger))*/
 
-        let start_time = Instant::now();
-        let stop_time = start_time + self.time_budget;
         let mut threads = Vec::new();
         for _ in 0..self.n_threads {
             let tree_ref = Arc::clone(&shared_tree);
+            let thread_history = history.clone();
             threads.push(
-                thread::spawn(move || parallel_search(tree_ref, stop_time, eval_fun/*, shared_logger*/))
+                thread::spawn(move || parallel_search(tree_ref, eval_fun, seed, thread_history/*, shared_logger*/))
             )
         }
         //let search_tree = astar_search(board, self.eval, self.time_budget);
         for t in threads {
             t.join().unwrap()
         }
-        let total_work_duration = Instant::now() - start_time;
         /*
         // We don't wait on the threads to finish, as they might still be working for a couple
         // For now, sleep and check the Arc count instead
@@ -52,20 +79,77 @@ impl ChessPlayer for AStarPrl {
         let final_shared_tree = Arc::try_unwrap(shared_tree)
                             .unwrap_or_else(|arc| panic!("More than one ref remains: {} left",
                                                          Arc::strong_count(&arc)));
-        let final_tree = final_shared_tree.into_inner().expect("Lock was poisoned");
-        super::finalize(&final_tree, self.eval, total_work_duration, logger)
+        let final_root = final_shared_tree.into_inner().expect("Lock was poisoned");
+        let final_tree = final_root.tree;
+        let total_work_duration = final_root.time_manager.elapsed();
+        super::print_tree_statistics(&final_tree, self.eval, total_work_duration, reused_nodes, logger);
+        if self.pondering {
+            self.ponderer = super::ponder_expected_reply(&final_tree, self.eval, self.seed, &history);
+        }
+
+        PrlTree {
+            tree:     final_tree,
+            duration: total_work_duration,
+        }
+    }
+
+    fn best_move(&self, data: &PrlTree) -> ChessMove {
+        super::best_move(&data.tree).unwrap()
+    }
+
+    fn search_info(&self, data: &PrlTree, best_move: ChessMove) -> Option<SearchInfo> {
+        Some(super::search_info(&data.tree, best_move, data.duration))
+    }
+
+    fn explanation(&self, data: &PrlTree, best_move: ChessMove) -> Option<Explanation> {
+        Some(super::explanation(&data.tree, best_move, self.eval, data.duration))
+    }
+
+    fn set_time_budget(&mut self, time_budget: Duration) {
+        self.time = Allotment::PerMove(time_budget);
+    }
+
+    fn set_clock(&mut self, clock: &ClockInfo) {
+        self.time = Allotment::Clock(*clock);
+    }
+
+    fn set_seed(&mut self, seed: Seed) {
+        self.seed = seed;
+    }
+
+    fn ponder(&mut self, board: &Board, history: &PositionHistory) {
+        let history = history.leading_to(board);
+        self.ponderer = Some(Ponderer::start(*board, None, self.eval, self.seed, history));
+    }
+
+    fn can_ponder(&self) -> bool {
+        true
+    }
+
+    fn stop_pondering(&mut self) -> Option<ChessMove> {
+        let tree = self.ponderer.take()?.finish();
+        super::best_move(&tree)
     }
 }
 
 type SeqTree = super::SearchTree;
 type Shared<T> = Mutex<T>;
-type SharedTree = Shared<SeqTree>;
+type SharedTree = Shared<SharedRoot>;
+
+/* The root moves are out of the heap while a thread searches them, so
+ * their latest scores are kept aside for the time manager.
+ */
+struct SharedRoot {
+    tree:         SeqTree,
+    scores:       Vec<eval::Score>,  /* indexed like the root moves */
+    time_manager: TimeManager,
+}
 
 //type PrlRoot = BinHeap<OrdByKey<Score, SeqBranch>>;
 type PrlRoot = SeqTree;
 
-fn init_root(init_board: Board, eval_fun: EvalFun) -> PrlRoot {
-    super::init_root(init_board, eval_fun)
+fn init_root(init_board: Board, eval_fun: EvalFun, seed: Seed, history: &mut PositionHistory) -> PrlRoot {
+    super::init_root(init_board, eval_fun, seed, history)
 }
 
 //fn lock_heap(shared_tree: &SharedTree) -> sync::LockResult<sync::MutexGuard<&mut super::NodeData>> {
@@ -131,15 +215,20 @@ fn safe_set(shared_tree: &Mutex<SeqTree>, elem: super::HeapEntry) -> LockResult<
 */
 fn parallel_search(
     shared_tree:   Arc<SharedTree>,
-    stop_time:     Instant,
-    eval_fun:      EvalFun/*,
+    eval_fun:      EvalFun,
+    seed:          Seed,
+    mut history:   PositionHistory/*,
     thread_logger: Arc<Mutex<&mut play::Logger>>*/)
 {
-    let root_board = shared_tree.lock().unwrap().board.clone();
+    let root_board = shared_tree.lock().unwrap().tree.board;
     let root_player = root_board.side_to_move();
-    while Instant::now() < stop_time {
+    loop {
         /* LOCK: BEGIN */
-        let mut root = shared_tree.lock().unwrap();
+        let mut shared_root = shared_tree.lock().unwrap();
+        let SharedRoot { tree: root, scores, time_manager } = &mut *shared_root;
+        if !super::keep_searching(time_manager, root, scores.iter().copied()) {
+            return;  /* time is up */
+        }
         let heap = &mut root.node_data;
         let mv_idx = match heap.pop() {
             Some(entry) => entry.mv_idx,
@@ -160,7 +249,7 @@ fn parallel_search(
         /* From this point on there should be automatic unlocking, as we're
          * not using anything from 'root'
          */
-        drop(root);  /* this should unlock */
+        drop(shared_root);  /* this should unlock */
         /* LOCK: END */
 
         /* Access the branch in unsafe mode.
@@ -169,29 +258,35 @@ fn parallel_search(
          */
         let branch: &mut _ = unsafe { &mut *branch_ptr };
         /* Perform the descent in lock-free mode, starting from the branch */
-        let new_scores = super::continue_descent(branch, &root_board, eval_fun);
+        history.push(&root_board, branch.mv);
+        let new_scores = super::continue_descent(branch, &root_board, eval_fun, seed, &mut history);
+        history.pop();
 
         /* Update the root */
         /* Need to lock again here */
         /* LOCK: BEGIN */
-        let mut root = shared_tree.lock().unwrap();
-        let heap = &mut root.node_data;
+        let mut shared_root = shared_tree.lock().unwrap();
+        let score = new_scores.get(root_player);
+        shared_root.scores[mv_idx] = score;
         let new_entry = super::HeapEntry {
-            score: new_scores.get(root_player),
+            score,
             mv_idx
         };
-        heap.push(new_entry);
-        drop(root);  /* this should unlock */
+        shared_root.tree.node_data.push(new_entry);
+        drop(shared_root);  /* this should unlock */
         /* LOCK: END */
     }
 }
 
 #[allow(dead_code)]
-pub fn parallel_player(time_budget: Duration, n_threads: ThreadCount) -> impl ChessPlayer {
+pub fn parallel_player(time_budget: Duration, n_threads: ThreadCount, pondering: bool, seed: Seed) -> AStarPrl {
     AStarPrl {
-        time_budget,
-        eval: eval::classic_eval,
-        n_threads
+        time:     Allotment::PerMove(time_budget),
+        eval:     eval::classic_eval,
+        n_threads,
+        seed,
+        pondering,
+        ponderer: None,
     }
 }
 
@@ -0,0 +1,220 @@
+use chess::Color;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/* Chess clocks: each player has its own time, which only runs while it
+ * thinks. The game is lost by the player whose time runs out.
+ */
+
+pub type ClockErr = String;
+
+/********** TimeControl **********/
+
+/* A base time and an increment added after each move, e.g. "60+0.5".
+ * With a number of moves, e.g. "40/60", the base time is added again
+ * each time that many moves are played.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimeControl {
+    pub base:      Duration,
+    pub increment: Duration,
+    pub moves:     Option<u32>,  /* None for the whole game */
+}
+
+/* "[moves/]base[+increment]", in seconds */
+impl FromStr for TimeControl {
+    type Err = ClockErr;
+
+    fn from_str(s: &str) -> Result<Self, ClockErr> {
+        let invalid = || format!("\"{}\" is not a valid time control (e.g. 60+0.5 or 40/120)", s);
+        /* Negative, NaN, infinite or too large times are rejected */
+        let seconds = |secs: &str| secs.parse::<f64>()
+                                       .ok()
+                                       .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                                       .ok_or_else(invalid);
+
+        let (moves, rest) = match s.split_once('/') {
+            Some((moves, rest)) => {
+                let moves = moves.parse::<u32>()
+                                 .ok()
+                                 .filter(|moves| *moves > 0)
+                                 .ok_or_else(invalid)?;
+                (Some(moves), rest)
+            }
+            None => (None, s),
+        };
+        let (base, increment) = match rest.split_once('+') {
+            Some((base, increment)) => (seconds(base)?, seconds(increment)?),
+            None                    => (seconds(rest)?, Duration::from_secs(0)),
+        };
+
+        Ok(TimeControl {
+            base,
+            increment,
+            moves,
+        })
+    }
+}
+
+/* Same format as the PGN TimeControl tag */
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(moves) = self.moves {
+            write!(f, "{}/", moves)?;
+        }
+        write!(f, "{}", self.base.as_secs_f64())?;
+        if self.increment > Duration::from_secs(0) {
+            write!(f, "+{}", self.increment.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+/********** ClockInfo **********/
+
+/* What a player is told of the clocks before it moves */
+#[derive(Clone, Copy, Debug)]
+pub struct ClockInfo {
+    pub time_left:          Duration,
+    pub opponent_time_left: Duration,
+    pub increment:          Duration,
+    pub moves_to_go:        Option<u32>,  /* before the base time is added again */
+}
+
+/********** Clock **********/
+
+#[derive(Clone, Copy)]
+pub struct Clock {
+    control:    TimeControl,
+    time_left:  [Duration; 2],  /* indexed by color */
+    moves_made: [u32; 2],
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        Clock {
+            control,
+            time_left:  [control.base; 2],
+            moves_made: [0; 2],
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    pub fn time_left(&self, player: Color) -> Duration {
+        self.time_left[player.to_index()]
+    }
+
+    pub fn info(&self, player: Color) -> ClockInfo {
+        let moves_made = self.moves_made[player.to_index()];
+        ClockInfo {
+            time_left:          self.time_left(player),
+            opponent_time_left: self.time_left(!player),
+            increment:          self.control.increment,
+            moves_to_go:        self.control.moves.map(|moves| moves - moves_made % moves),
+        }
+    }
+
+    /* Charges the player for the time it took to move.
+     * Returns false if its flag fell, in which case its time is left at zero.
+     */
+    pub fn punch(&mut self, player: Color, elapsed: Duration) -> bool {
+        let idx = player.to_index();
+        if elapsed > self.time_left[idx] {
+            self.time_left[idx] = Duration::from_secs(0);
+            return false;
+        }
+
+        self.time_left[idx] -= elapsed;
+        self.time_left[idx] += self.control.increment;
+        self.moves_made[idx] += 1;
+        if let Some(moves) = self.control.moves {
+            if self.moves_made[idx].is_multiple_of(moves) {
+                self.time_left[idx] += self.control.base;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    fn control(s: &str) -> TimeControl {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn time_controls_are_read_and_written_in_seconds() {
+        assert_eq!(control("60+0.5"), TimeControl { base: secs(60.), increment: secs(0.5), moves: None });
+        assert_eq!(control("40/120"), TimeControl { base: secs(120.), increment: secs(0.), moves: Some(40) });
+        assert_eq!(control("40/120").to_string(), "40/120");
+        assert_eq!(control("60+0.5").to_string(), "60+0.5");
+        for s in ["", "0/60", "-1", "60+", "x/60", "inf", "NaN+1"] {
+            assert!(s.parse::<TimeControl>().is_err(), "{} should not be read", s);
+        }
+    }
+
+    #[test]
+    fn punching_charges_only_the_player_who_moved() {
+        let mut clock = Clock::new(control("60"));
+        assert!(clock.punch(Color::White, secs(2.5)));
+        assert_eq!(clock.time_left(Color::White), secs(57.5));
+        assert_eq!(clock.time_left(Color::Black), secs(60.));
+
+        assert!(clock.punch(Color::Black, secs(10.)));
+        assert_eq!(clock.time_left(Color::Black), secs(50.));
+
+        let info = clock.info(Color::White);
+        assert_eq!((info.time_left, info.opponent_time_left), (secs(57.5), secs(50.)));
+        assert_eq!(info.moves_to_go, None);
+    }
+
+    #[test]
+    fn the_increment_comes_after_each_move() {
+        let mut clock = Clock::new(control("10+2"));
+        assert!(clock.punch(Color::White, secs(3.)));
+        assert_eq!(clock.time_left(Color::White), secs(9.));
+        assert!(clock.punch(Color::White, secs(0.)));
+        assert_eq!(clock.time_left(Color::White), secs(11.));
+        assert_eq!(clock.info(Color::White).increment, secs(2.));
+    }
+
+    #[test]
+    fn the_base_time_comes_back_after_each_period() {
+        let mut clock = Clock::new(control("2/60"));
+        assert_eq!(clock.info(Color::White).moves_to_go, Some(2));
+
+        assert!(clock.punch(Color::White, secs(20.)));
+        assert_eq!(clock.time_left(Color::White), secs(40.));
+        assert_eq!(clock.info(Color::White).moves_to_go, Some(1));
+
+        assert!(clock.punch(Color::White, secs(30.)));
+        assert_eq!(clock.time_left(Color::White), secs(70.));
+        assert_eq!(clock.info(Color::White).moves_to_go, Some(2));
+
+        /* Each player has its own periods */
+        assert_eq!(clock.info(Color::Black).moves_to_go, Some(2));
+        assert_eq!(clock.info(Color::Black).time_left, secs(60.));
+    }
+
+    #[test]
+    fn the_flag_falls_when_the_time_is_exceeded() {
+        let mut clock = Clock::new(control("5+1"));
+        /* Exactly all the time left is still in time */
+        assert!(clock.punch(Color::White, secs(5.)));
+        assert_eq!(clock.time_left(Color::White), secs(1.));
+
+        assert!(!clock.punch(Color::White, secs(1.5)));
+        assert_eq!(clock.time_left(Color::White), secs(0.));
+        assert_eq!(clock.time_left(Color::Black), secs(5.));
+    }
+}
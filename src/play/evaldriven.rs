@@ -1,19 +1,23 @@
-use chess::{Board, MoveGen, ChessMove};
+use chess::{Board, BoardStatus, MoveGen, ChessMove};
 use crate::eval::{EvalFun, Score};
 use crate::eval;
-use super::ChessPlayer;
+use super::{ChessPlayer, PositionHistory, Seed};
 use rand::seq::IteratorRandom;
 use rand::Rng;
-use rand::rngs::ThreadRng;
 use crate::utils;
 
-fn pick_best_move<R: Rng>(board: &Board, eval: EvalFun, rng: &mut R) -> ChessMove {
+/* Moves that repeat a position of the history are draws */
+fn pick_best_move<R: Rng>(board: &Board, eval: EvalFun, history: &PositionHistory, rng: &mut R) -> ChessMove {
     let curr_player = board.side_to_move();
 
     /* this is a closure */
     let eval_move = |mv: &ChessMove| -> Score {
         let state_after_move = board.make_move_new(mv.clone());
-        eval(&state_after_move, curr_player)
+        let is_draw = state_after_move.status() == BoardStatus::Ongoing
+                      && history.is_search_draw_after(board, *mv);
+
+        if is_draw { eval::DRAW_SCORE }
+        else { eval(&state_after_move, curr_player) }
     };
 
     let movegen = MoveGen::new_legal(&board);
@@ -24,23 +28,27 @@ fn pick_best_move<R: Rng>(board: &Board, eval: EvalFun, rng: &mut R) -> ChessMov
 #[derive(Clone)]
 pub struct EvalPlayer {
     eval: EvalFun,
-    rng:  ThreadRng
+    seed: Seed,
 }
 
-pub fn eval_driven_player(eval: EvalFun) -> EvalPlayer {
+pub fn eval_driven_player(eval: EvalFun, seed: Seed) -> EvalPlayer {
     EvalPlayer {
         eval,
-        rng: rand::thread_rng()
+        seed,
     }
 }
 
 #[allow(dead_code)]
-pub fn classic_eval_player() -> EvalPlayer {
-    eval_driven_player(eval::classic_eval)
+pub fn classic_eval_player(seed: Seed) -> EvalPlayer {
+    eval_driven_player(eval::classic_eval, seed)
 }
 
 impl ChessPlayer for EvalPlayer {
-    fn pick_move(&mut self, board: &Board, _logger: &mut super::Logger) -> ChessMove {
-        pick_best_move(board, self.eval, &mut self.rng)
+    fn pick_move(&mut self, board: &Board, history: &PositionHistory, _logger: &mut super::Logger) -> ChessMove {
+        pick_best_move(board, self.eval, history, &mut super::decision_rng(self.seed, board))
+    }
+
+    fn set_seed(&mut self, seed: Seed) {
+        self.seed = seed;
     }
 }
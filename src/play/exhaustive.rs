@@ -1,36 +1,53 @@
 use chess::{Board, BoardStatus, ChessMove, MoveGen};
 use rand::seq::IteratorRandom;
 use rand::Rng;
-use rand::rngs::ThreadRng;
 use crate::utils;
 use crate::eval;
 use crate::eval::EvalFun;
-use super::{MoveCount, ChessPlayer};
+use super::{MoveCount, ChessPlayer, PositionHistory, Seed};
 use crate::logging::LogLevel;
 
 pub struct ExhaustiveSearch {
     depth: MoveCount,
     eval:  EvalFun,
-    rng:   ThreadRng
+    seed:  Seed,
 }
 
 #[allow(unused_must_use)]
 impl ChessPlayer for ExhaustiveSearch {
-    fn pick_move(&mut self, board: &Board, logger: &mut super::Logger) -> ChessMove {
+    fn pick_move(&mut self, board: &Board, history: &PositionHistory, logger: &mut super::Logger) -> ChessMove {
         let init_log_level = LogLevel::Debug;
         log_nol!(logger, init_log_level,
                  "\n{{start:{}, ", (self.eval)(board, board.side_to_move()));
 
-        let (best_move, _best_board) = exhaustive_search(board,
-                                                         self.eval,
-                                                         self.depth,
-                                                         &mut self.rng,
-                                                         logger,
-                                                         init_log_level);
+        let (best_move, _best_leaf) = exhaustive_search(board,
+                                                        self.eval,
+                                                        self.depth,
+                                                        &mut history.leading_to(board),
+                                                        &mut super::decision_rng(self.seed, board),
+                                                        logger,
+                                                        init_log_level);
         log!(logger, init_log_level, "}}");
         info!(logger, "Best move: {}", best_move);
         return best_move;
     }
+
+    fn set_seed(&mut self, seed: Seed) {
+        self.seed = seed;
+    }
+}
+
+/* Where a line of the search stops */
+struct Leaf {
+    board: Board,
+    drawn: bool,  /* repeats a position of the line or of the history */
+}
+
+impl Leaf {
+    fn value(&self, eval_fun: EvalFun, player: chess::Color) -> eval::Score {
+        if self.drawn { eval::DRAW_SCORE }
+        else { eval_fun(&self.board, player) }
+    }
 }
 
 #[allow(unused_must_use)]
@@ -38,48 +55,49 @@ fn exhaustive_search<R: Rng>(
     board:     &Board,
     eval_fun:  EvalFun,
     depth:     MoveCount,
+    history:   &mut PositionHistory,
     rng:       &mut R,
     logger:    &mut super::Logger,
     log_level: LogLevel)
-    -> (ChessMove, Board)
+    -> (ChessMove, Leaf)
 {
-    let base_case = |mv| board.make_move_new(mv);
-
-    let mut rec_case = |mv| {
+    let mut leaf_for = |mv| {
         let next_board = board.make_move_new(mv);
+        history.push(board, mv);
 
-        match next_board.status() {
-            BoardStatus::Ongoing => {
+        let leaf = match next_board.status() {
+            BoardStatus::Ongoing if history.is_search_draw(&next_board) =>
+                Leaf { board: next_board, drawn: true },  // stop recursion on draws
+            BoardStatus::Ongoing if depth > 1 => {
                 log_nol!(logger, log_level, "{}:{{", mv);
-                let r = exhaustive_search(&next_board, eval_fun, depth-1, rng, logger, log_level.lower()).1;
+                let r = exhaustive_search(&next_board, eval_fun, depth-1, history, rng, logger, log_level.lower()).1;
                 log_nol!(logger, log_level, "}}, ");
                 r
             }
-            _                    => next_board  // stop recursion if game is over
-        }
-    };
+            _ => Leaf { board: next_board, drawn: false },  // stop recursion if game is over
+        };
 
-    let mut board_for = |mv|
-        if depth == 1 { base_case(mv) }
-        else { rec_case(mv) };
+        history.pop();
+        leaf
+    };
 
     let player = board.side_to_move();
 
     /* Here we need to collect to avoid multiple mutable borrows of the logger by the closures */
-    let board_and_moves: Vec<_> = MoveGen::new_legal(board).map(|mv| (mv, board_for(mv))).collect();
+    let leaf_and_moves: Vec<_> = MoveGen::new_legal(board).map(|mv| (mv, leaf_for(mv))).collect();
 
-    let best_moves = utils::iter::all_maxs_by_key(board_and_moves.into_iter(),
-                                                  |(mv, new_board)| { let v = eval_fun(new_board, player); log_nol!(logger, log_level, "{}:{}, ", mv, v); v });
+    let best_moves = utils::iter::all_maxs_by_key(leaf_and_moves.into_iter(),
+                                                  |(mv, leaf)| { let v = leaf.value(eval_fun, player); log_nol!(logger, log_level, "{}:{}, ", mv, v); v });
     best_moves.into_iter()
               .choose(rng)
               .unwrap()
 }
 
 #[allow(dead_code)]
-pub fn exhaustive_search_player(depth: MoveCount) -> impl ChessPlayer {
+pub fn exhaustive_search_player(depth: MoveCount, seed: Seed) -> impl ChessPlayer {
     ExhaustiveSearch {
         depth,
         eval: eval::classic_eval,
-        rng:  rand::thread_rng()
+        seed,
     }
 }
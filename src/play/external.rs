@@ -0,0 +1,356 @@
+use chess::{Board, ChessMove, Color};
+use super::{ChessPlayer, Decision, PositionHistory, SearchInfo};
+use super::clock::ClockInfo;
+use crate::eval;
+use crate::uci;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/* A player backed by an external UCI engine, run as a subprocess.
+ *
+ * The engine gets the game as its initial position and the moves played
+ * since, so that it can tell repetitions, and either a fixed time per
+ * move ("go movetime"), or the clocks in timed games ("go wtime ...").
+ * Its output is read by a separate thread, so that waiting for an
+ * answer can time out.
+ */
+
+pub type EngineErr = String;
+
+pub struct ExternalEnginePlayer {
+    name:        String,
+    child:       Child,
+    stdin:       ChildStdin,
+    lines:       Receiver<String>,
+    time_budget: Duration,
+    clock:       Option<ClockInfo>,  /* the engine manages its own time in timed games */
+}
+
+/* For 'uci' and 'isready' */
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/* Extra time given to the engine to answer, on top of its budget */
+const ANSWER_MARGIN: Duration = Duration::from_secs(2);
+/* Time given to the engine to exit after 'quit' */
+const QUIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+impl ExternalEnginePlayer {
+    /* The command is the path to the engine, optionally followed by its arguments */
+    pub fn start(command: &str, time_budget: Duration) -> Result<Self, EngineErr> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("Missing engine command")?;
+
+        let mut child = Command::new(program)
+                                .args(words)
+                                .stdin(Stdio::piped())
+                                .stdout(Stdio::piped())
+                                .stderr(Stdio::null())
+                                .spawn()
+                                .map_err(|reason| format!("Couldn't start engine \"{}\": {}", command, reason))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() { return; },
+                    Err(_)   => return,
+                }
+            }
+            /* end of output, the channel is closed when the sender is dropped */
+        });
+
+        let mut engine = ExternalEnginePlayer {
+            name: String::from(command),
+            child,
+            stdin,
+            lines,
+            time_budget,
+            clock: None,
+        };
+
+        engine.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let line = engine.next_line(deadline, "uciok")?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = String::from(name.trim());
+            }
+            if line.trim() == "uciok" {
+                break;
+            }
+        }
+        engine.sync()?;
+
+        Ok(engine)
+    }
+
+    /* The history must lead to the board, or it is replaced by the board alone */
+    pub fn request_move(&mut self, board: &Board, history: &PositionHistory) -> Result<(ChessMove, SearchInfo), EngineErr> {
+        let start = Instant::now();
+        self.send(&position_command(&history.leading_to(board)))?;
+        let max_time = match self.clock {
+            Some(clock) => {
+                self.send(&go_with_clock(&clock, board.side_to_move()))?;
+                clock.time_left
+            }
+            None => {
+                self.send(&format!("go movetime {}", self.time_budget.as_millis()))?;
+                self.time_budget
+            }
+        };
+
+        let mut deadline = start + max_time + ANSWER_MARGIN;
+        let mut stop_sent = false;
+        let mut info = SearchInfo {
+            score:     None,
+            best_line: Vec::new(),
+            nodes:     0,
+            depth:     0,
+            duration:  Duration::from_secs(0),
+        };
+
+        loop {
+            let line = match self.next_line(deadline, "bestmove") {
+                Ok(line) => line,
+                Err(_) if !stop_sent && self.is_alive() => {
+                    /* Maybe the engine doesn't handle movetime, ask it to stop */
+                    self.send("stop")?;
+                    stop_sent = true;
+                    deadline = Instant::now() + ANSWER_MARGIN;
+                    continue;
+                }
+                Err(reason) => {
+                    /* A late answer would be taken for the answer to the next request */
+                    let _ = self.child.kill();
+                    return Err(reason);
+                }
+            };
+
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("info") => parse_info(tokens, board, &mut info),
+
+                Some("bestmove") => {
+                    let mv_str = tokens.next().unwrap_or("");
+                    let mv = uci::parse_coord_move(board, mv_str)
+                                 .ok_or(format!("Engine {} played an illegal move: \"{}\"", self.name, mv_str))?;
+
+                    info.duration = start.elapsed();
+                    if info.best_line.first() != Some(&mv) {
+                        info.best_line = vec![mv];
+                    }
+                    return Ok((mv, info));
+                }
+
+                _ => { /* not for us */ }
+            }
+        }
+    }
+
+    /* Waits until the engine has processed all the commands sent so far */
+    fn sync(&mut self) -> Result<(), EngineErr> {
+        self.send("isready")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        while self.next_line(deadline, "readyok")?.trim() != "readyok" { }
+        Ok(())
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), EngineErr> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|_| self.death_reason())
+    }
+
+    /* `expected` only tells what we were waiting for, in case of error */
+    fn next_line(&mut self, deadline: Instant, expected: &str) -> Result<String, EngineErr> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self.lines.recv_timeout(timeout) {
+            Ok(line)                            => Ok(line),
+            Err(RecvTimeoutError::Timeout)      => Err(format!("Engine {} did not answer in time (waiting for {})",
+                                                               self.name, expected)),
+            Err(RecvTimeoutError::Disconnected) => Err(self.death_reason()),
+        }
+    }
+
+    fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    fn death_reason(&mut self) -> EngineErr {
+        /* The output may be closed slightly before the process ends */
+        let deadline = Instant::now() + QUIT_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                return format!("Engine {} died ({})", self.name, status);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        format!("Engine {} closed its output", self.name)
+    }
+}
+
+impl Drop for ExternalEnginePlayer {
+    fn drop(&mut self) {
+        if self.send("quit").is_ok() {
+            let deadline = Instant::now() + QUIT_TIMEOUT;
+            while Instant::now() < deadline {
+                if !self.is_alive() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/* position (startpos | fen <fen>) [moves <move>...] */
+fn position_command(history: &PositionHistory) -> String {
+    let mut command = if *history.init_board() == Board::default() {
+        String::from("position startpos")
+    }
+    else {
+        format!("position fen {}", history.init_board())
+    };
+
+    if !history.moves().is_empty() {
+        command.push_str(" moves");
+        for mv in history.moves() {
+            command.push_str(&format!(" {}", mv));
+        }
+    }
+    command
+}
+
+fn go_with_clock(clock: &ClockInfo, player: Color) -> String {
+    let (wtime, btime) = match player {
+        Color::White => (clock.time_left, clock.opponent_time_left),
+        Color::Black => (clock.opponent_time_left, clock.time_left),
+    };
+    let inc = clock.increment.as_millis();
+
+    let mut go = format!("go wtime {} btime {} winc {} binc {}", wtime.as_millis(), btime.as_millis(), inc, inc);
+    if let Some(moves_to_go) = clock.moves_to_go {
+        go.push_str(&format!(" movestogo {}", moves_to_go));
+    }
+    go
+}
+
+/* Only the fields that make sense for a SearchInfo are read */
+fn parse_info(mut tokens: std::str::SplitWhitespace, board: &Board, info: &mut SearchInfo) {
+    while let Some(token) = tokens.next() {
+        match token {
+            "depth" => info.depth = tokens.next().and_then(|d| d.parse().ok()).unwrap_or(info.depth),
+            "nodes" => info.nodes = tokens.next().and_then(|n| n.parse().ok()).unwrap_or(info.nodes),
+
+            "score" => {
+                info.score = match (tokens.next(), tokens.next().and_then(|v| v.parse::<i32>().ok())) {
+                    (Some("cp"), Some(cp)) =>
+                        Some((cp / uci::CENTIPAWNS_PER_POINT)
+                                 .clamp(eval::Score::MIN as i32 + 1, eval::Score::MAX as i32 - 1) as eval::Score),
+                    (Some("mate"), Some(moves)) =>
+                        Some(if moves > 0 { eval::Score::MAX } else { eval::Score::MIN }),
+                    _ => info.score,
+                };
+            }
+
+            /* The pv comes last */
+            "pv" => {
+                let mut curr_board = *board;
+                info.best_line.clear();
+                for mv_str in tokens.by_ref() {
+                    match uci::parse_coord_move(&curr_board, mv_str) {
+                        Some(mv) => {
+                            info.best_line.push(mv);
+                            curr_board = curr_board.make_move_new(mv);
+                        }
+                        None => break,
+                    }
+                }
+            }
+
+            _ => { }
+        }
+    }
+}
+
+impl ChessPlayer for ExternalEnginePlayer {
+    /* Drivers ask engines with 'decide', which tells when they fail to play */
+    fn pick_move(&mut self, board: &Board, history: &PositionHistory, logger: &mut super::Logger) -> ChessMove {
+        match self.decide(board, history, logger) {
+            Decision::Play(mv) => mv,
+            _                  => panic!("Engine {} failed to play a move", self.name),
+        }
+    }
+
+    fn set_time_budget(&mut self, time_budget: Duration) {
+        self.time_budget = time_budget;
+        self.clock = None;
+    }
+
+    fn set_clock(&mut self, clock: &ClockInfo) {
+        self.clock = Some(*clock);
+    }
+
+    fn decide(&mut self, board: &Board, history: &PositionHistory, logger: &mut super::Logger) -> Decision {
+        self.decide_with_info(board, history, logger).0
+    }
+
+    /* An engine that can't play anymore forfeits the game */
+    fn decide_with_info(&mut self, board: &Board, history: &PositionHistory, logger: &mut super::Logger) -> (Decision, Option<SearchInfo>) {
+        match self.request_move(board, history) {
+            Ok((mv, info)) => {
+                info!(logger, "{} plays {} after {}ms", self.name, mv, info.duration.as_millis());
+                (Decision::Play(mv), Some(info))
+            }
+            Err(reason) => {
+                warn!(logger, "{}, resigning", reason);
+                (Decision::Resign, None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn history_after(init_board: Board, moves: &[&str]) -> PositionHistory {
+        let mut history = PositionHistory::new(&init_board);
+        let mut board = init_board;
+        for mv_str in moves {
+            let mv = uci::parse_coord_move(&board, mv_str).unwrap();
+            history.push(&board, mv);
+            board = board.make_move_new(mv);
+        }
+        history
+    }
+
+    #[test]
+    fn games_from_the_start_position_send_startpos() {
+        assert_eq!(position_command(&history_after(Board::default(), &[])), "position startpos");
+        assert_eq!(position_command(&history_after(Board::default(), &["g1f3", "g8f6", "f3g1", "f6g8"])),
+                   "position startpos moves g1f3 g8f6 f3g1 f6g8");
+    }
+
+    #[test]
+    fn games_from_other_positions_send_their_fen() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let board = Board::from_str(fen).unwrap();
+        assert_eq!(position_command(&history_after(board, &["e2e4"])),
+                   format!("position fen {} moves e2e4", board));
+    }
+
+    #[test]
+    fn the_history_is_dropped_when_it_does_not_lead_to_the_board() {
+        let history = history_after(Board::default(), &["e2e4"]);
+        let board = Board::default().make_move_new(uci::parse_coord_move(&Board::default(), "d2d4").unwrap());
+        assert_eq!(position_command(&history.leading_to(&board)), format!("position fen {}", board));
+    }
+}
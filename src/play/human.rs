@@ -0,0 +1,123 @@
+use chess::{Board, ChessMove, Color, MoveGen, Piece, Square};
+use super::{ChessPlayer, Decision, PositionHistory};
+use crate::pgn;
+use std::io::{self, BufRead, Write};
+
+/* A player that asks a human for its moves over the terminal.
+ * Moves can be given in SAN ("Nf3") or in coordinate notation ("g1f3").
+ */
+
+pub struct HumanPlayer<R: BufRead, W: Write> {
+    input:  R,
+    output: W,
+}
+
+pub fn terminal_player() -> HumanPlayer<io::BufReader<io::Stdin>, io::Stdout> {
+    HumanPlayer {
+        input:  io::BufReader::new(io::stdin()),
+        output: io::stdout(),
+    }
+}
+
+const HELP_MESSAGE: &str = "\
+Enter a move in SAN (e.g. Nf3, exd5, O-O) or coordinate notation (e.g. g1f3, e7e8q)
+Commands:
+  moves    show the legal moves
+  board    show the board again
+  undo     take back your last move
+  resign   give up the game
+  help     show this message";
+
+impl<R: BufRead, W: Write> ChessPlayer for HumanPlayer<R, W> {
+    /* Drivers ask humans with 'decide', which tells when nobody is there anymore */
+    fn pick_move(&mut self, board: &Board, history: &PositionHistory, logger: &mut super::Logger) -> ChessMove {
+        match self.decide(board, history, logger) {
+            Decision::Play(mv) => mv,
+            _                  => panic!("The human player gave no move"),
+        }
+    }
+
+    #[allow(unused_must_use)]
+    fn decide(&mut self, board: &Board, _history: &PositionHistory, _logger: &mut super::Logger) -> Decision {
+        match self.prompt(board) {
+            Some(decision) => decision,
+            None => {
+                /* Nobody is there to play anymore */
+                writeln!(self.output, "\nEnd of input, resigning");
+                Decision::Resign
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> HumanPlayer<R, W> {
+    /* Asks until the human decides, None at the end of the input */
+    #[allow(unused_must_use)]
+    fn prompt(&mut self, board: &Board) -> Option<Decision> {
+        writeln!(self.output, "\n{}", display_board(board));
+
+        loop {
+            write!(self.output, "{:?} to play> ", board.side_to_move());
+            self.output.flush();
+
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_)          => { }
+            }
+
+            match line.trim() {
+                "" => { }
+
+                "help" => { writeln!(self.output, "{}", HELP_MESSAGE); }
+
+                "moves" => {
+                    let moves: Vec<_> = MoveGen::new_legal(board).map(|mv| format!("{}", mv)).collect();
+                    writeln!(self.output, "{}", moves.join(" "));
+                }
+
+                "board" => { writeln!(self.output, "{}", display_board(board)); }
+
+                "undo" => return Some(Decision::Undo),
+
+                "resign" => return Some(Decision::Resign),
+
+                mv_str =>
+                    match pgn::parse_move(board, mv_str) {
+                        Ok(mv)      => return Some(Decision::Play(mv)),
+                        Err(reason) => { writeln!(self.output, "{} (type 'help' for help)", reason); }
+                    }
+            }
+        }
+    }
+}
+
+/* White pieces are uppercase, black ones lowercase */
+fn display_board(board: &Board) -> String {
+    let mut res = String::new();
+
+    for rank_idx in (0..8).rev() {
+        res.push_str(&format!("{} ", rank_idx + 1));
+        for file_idx in 0..8 {
+            let sq = Square::make_square(chess::Rank::from_index(rank_idx),
+                                         chess::File::from_index(file_idx));
+            let sq_rep = match (board.piece_on(sq), board.color_on(sq)) {
+                (Some(piece), Some(color)) => piece_char(piece, color),
+                _                          => '.',
+            };
+            res.push(' ');
+            res.push(sq_rep);
+        }
+        res.push('\n');
+    }
+    res.push_str("   a b c d e f g h");
+
+    res
+}
+
+fn piece_char(piece: Piece, color: Color) -> char {
+    piece.to_string(color)
+         .chars()
+         .next()
+         .unwrap()
+}
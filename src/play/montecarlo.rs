@@ -1,13 +1,14 @@
 use chess::{Board, MoveGen, ChessMove};
 use super::evaldriven;
 use super::evaldriven::EvalPlayer;
-use super::ChessPlayer;
+use super::{Candidate, ChessPlayer, DebugPlayer, Explanation, PositionHistory, SearchInfo, Seed};
+use super::clock::ClockInfo;
+use super::timeman::{Allotment, TimeManager};
 use rand::Rng;
-use rand::rngs::ThreadRng;
 use rand::distributions::{Distribution, WeightedIndex};
 use crate::play;
 use play::MoveCount;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use std::fmt::Display;
 use crate::logging;
 
@@ -18,6 +19,7 @@ use stats::MoveEval;
 
 struct Root<S> {
     init_board: Board,
+    history:    PositionHistory,  /* leading to init_board */
     root_node:  Node<S>,
 }
 
@@ -27,9 +29,10 @@ struct Node<S> {
 
 type RunCount = u16;
 
-fn new_root<M: MoveEval>(board: &Board, move_eval: &M) -> Root<M::Stats> {
+fn new_root<M: MoveEval>(board: &Board, history: PositionHistory, move_eval: &M) -> Root<M::Stats> {
     Root {
         init_board: board.clone(),
+        history,
         root_node:  new_node(board, move_eval),
     }
 }
@@ -91,15 +94,15 @@ fn run_once<P: ChessPlayer, M: MoveEval, R: Rng>(
     let stats_to_update = &mut root_node.moves[move_idx].1;
 
     let init_board = &root.init_board;
-    let board_after_move = init_board.make_move_new(first_move);
-    let mut game = play::Game {
-        init_board:  init_board.clone(),
-        final_board: board_after_move,
-        moves:       vec![first_move],
-    };
+    let mut game = play::Game::continuing(*init_board, root.history.clone());
+    game.play_move(first_move);
+
+    /* Otherwise the rollouts from a position would all be the same */
+    white_rollout.set_seed(rng.gen());
+    black_rollout.set_seed(rng.gen());
 
     let mut rollout_logger = logging::ignore_all();  // ignore any output
-    game.continue_playing(white_rollout, black_rollout, rollout_depth, &mut rollout_logger);
+    game.continue_playing(white_rollout, black_rollout, rollout_depth, &play::Arbiter::default(), &mut rollout_logger);
 
     let player = init_board.side_to_move();
     move_eval.update_stats(stats_to_update, player, game);
@@ -149,25 +152,32 @@ fn print_run_info<M, S>(root:      &Root<S>,
 
 fn run_monte_carlo_search<P, M, S, R>(
     board:          &Board,
+    history:        PositionHistory,
     move_eval:      &mut M,
-    time_budget:    Duration,
+    time:           &Allotment,
     white_rollout:  &mut P,
     black_rollout:  &mut P,
     rollout_depth:  MoveCount,
     rng:            &mut R,
     logger:         &mut super::Logger)
-    -> Root<S>
+    -> (Root<S>, RunCount, Duration)
     where
         P: ChessPlayer,
         M: MoveEval<Stats = S>,
         S: Display,
         R: Rng
 {
-    let start_time = Instant::now();
+    let mut time_manager = TimeManager::start(time, board);
     let mut n_runs = 0;
 
-    let mut root = new_root(board, move_eval);
-    while start_time.elapsed() < time_budget {
+    /* The statistics are not comparable between evaluations, so only a
+     * single legal move dominates.
+     */
+    let mut root = new_root(board, history, move_eval);
+    let single_move = root.root_node.moves.len() == 1;
+    while !root.root_node.moves.is_empty()
+          && time_manager.keep_searching(pick_best_move(&root, &*move_eval), single_move)
+    {
         run_once(&mut root,
                  move_eval,
                  white_rollout,
@@ -179,9 +189,10 @@ fn run_monte_carlo_search<P, M, S, R>(
         n_runs += 1;
     }
 
-    print_run_info(&root, move_eval, start_time.elapsed(), n_runs, logger);
+    let run_dur = time_manager.elapsed();
+    print_run_info(&root, move_eval, run_dur, n_runs, logger);
 
-    return root;
+    (root, n_runs, run_dur)
 }
 
 fn unsafe_cmp_partial_ord<T: PartialOrd>(a: &T, b: &T) -> std::cmp::Ordering {
@@ -209,35 +220,102 @@ fn pick_best_move<M: MoveEval>(root: &Root<M::Stats>, move_eval: M) -> ChessMove
 
 /*********** ChessPlayer definition *************/
 
-pub struct MonteCarlo1<P: ChessPlayer, M: MoveEval, R: Rng> {
+pub struct MonteCarlo1<P: ChessPlayer, M: MoveEval> {
     white_rollout: P,
     black_rollout: P,
     rollout_depth: MoveCount,
     move_eval:     M,
-    time_budget:   Duration,
-    rng:           R,
+    time:          Allotment,
+    seed:          Seed,
+}
+
+pub struct Runs<S> {
+    root:    Root<S>,
+    n_runs:  RunCount,
+    run_dur: Duration,
 }
 
-impl<P, M, S, R> ChessPlayer for MonteCarlo1<P, M, R>
+impl<P, M, S> DebugPlayer for MonteCarlo1<P, M>
     where
         P: ChessPlayer,
         M: MoveEval<Stats = S>,
-        S: Display,
-        R: Rng
+        S: Display
 {
-    fn pick_move(&mut self, board: &Board, logger: &mut super::Logger) -> ChessMove {
-        let res_root =
+    type DebugData = Runs<S>;
+
+    fn compute_move(&mut self, board: &Board, history: &PositionHistory, logger: &mut super::Logger) -> Runs<S> {
+        let (root, n_runs, run_dur) =
             run_monte_carlo_search(
                 board,
+                history.leading_to(board),
                 &mut self.move_eval,
-                self.time_budget,
+                &self.time,
                 &mut self.white_rollout,
                 &mut self.black_rollout,
                 self.rollout_depth,
-                &mut self.rng,
+                &mut super::decision_rng(self.seed, board),
                 logger);
 
-        pick_best_move(&res_root, &self.move_eval)
+        Runs {
+            root,
+            n_runs,
+            run_dur,
+        }
+    }
+
+    fn best_move(&self, runs: &Runs<S>) -> ChessMove {
+        pick_best_move(&runs.root, &self.move_eval)
+    }
+
+    /* The Monte Carlo values are not comparable to board evaluations,
+     * so we don't report any score.
+     */
+    fn search_info(&self, runs: &Runs<S>, best_move: ChessMove) -> Option<SearchInfo> {
+        Some(SearchInfo {
+            score:     None,
+            best_line: vec![best_move],
+            nodes:     runs.n_runs as u32,
+            depth:     self.rollout_depth as u16,
+            duration:  runs.run_dur,
+        })
+    }
+
+    /* Moves are rated by their statistics over the rollouts that started with them */
+    fn explanation(&self, runs: &Runs<S>, best_move: ChessMove) -> Option<Explanation> {
+        let mut rated_moves: Vec<_> = runs.root.root_node.moves.iter()
+                                                               .map(|(mv, stats)| (*mv, stats, self.move_eval.eval(stats)))
+                                                               .collect();
+        rated_moves.sort_by(|(mv_a, _, value_a), (mv_b, _, value_b)|
+                                unsafe_cmp_partial_ord(value_b, value_a)
+                                    .then((*mv_a != best_move).cmp(&(*mv_b != best_move))));
+
+        let candidates = rated_moves.into_iter()
+                                    .map(|(mv, stats, value)| Candidate {
+                                        mv,
+                                        score:     None,
+                                        rating:    format!("{} ~> {}", stats, value),
+                                        best_line: vec![mv],
+                                        nodes:     None,
+                                    })
+                                    .collect();
+
+        Some(Explanation {
+            info:      self.search_info(runs, best_move).unwrap(),
+            candidates,
+            dot_graph: None,
+        })
+    }
+
+    fn set_time_budget(&mut self, time_budget: Duration) {
+        self.time = self.time.replaced_by(Allotment::PerMove(time_budget));
+    }
+
+    fn set_clock(&mut self, clock: &ClockInfo) {
+        self.time = self.time.replaced_by(Allotment::Clock(*clock));
+    }
+
+    fn set_seed(&mut self, seed: Seed) {
+        self.seed = seed;
     }
 }
 
@@ -245,25 +323,27 @@ impl<P, M, S, R> ChessPlayer for MonteCarlo1<P, M, R>
 
 pub fn monte_carlo1<P: ChessPlayer + Clone>(
     rollout_player: P,
-    time_budget:    Duration,
-    rollout_depth:  MoveCount)
-    -> MonteCarlo1<P, stats::DefaultEval, ThreadRng>
+    time:           Allotment,
+    rollout_depth:  MoveCount,
+    seed:           Seed)
+    -> MonteCarlo1<P, stats::DefaultEval>
 {
-    MonteCarlo1::<P, _, _> {
+    MonteCarlo1::<P, _> {
         white_rollout: rollout_player.clone(),
         black_rollout: rollout_player,
         move_eval:     stats::DefaultEval::default(),
         rollout_depth,
-        time_budget,
-        rng: rand::thread_rng(),
+        time,
+        seed,
     }
 }
 
-const DEFAULT_TIME_BUDGET: Duration = Duration::from_millis(500);
-const DEFAULT_ROLLOUT_DEPTH: MoveCount = 2*20;
+pub const DEFAULT_TIME_BUDGET: Duration = Duration::from_millis(500);
+pub const DEFAULT_ROLLOUT_DEPTH: MoveCount = 2*20;
 #[allow(dead_code)]
-pub fn basic_monte_carlo1() -> MonteCarlo1<EvalPlayer, stats::DefaultEval, ThreadRng> {
-    monte_carlo1(evaldriven::classic_eval_player(),
-                 DEFAULT_TIME_BUDGET,
-                 DEFAULT_ROLLOUT_DEPTH)
+pub fn basic_monte_carlo1(seed: Seed) -> MonteCarlo1<EvalPlayer, stats::DefaultEval> {
+    monte_carlo1(evaldriven::classic_eval_player(seed),
+                 Allotment::PerMove(DEFAULT_TIME_BUDGET),
+                 DEFAULT_ROLLOUT_DEPTH,
+                 seed)
 }
@@ -1,7 +1,7 @@
 use chess::{Board, MoveGen, ChessMove};
 use rand::seq::IteratorRandom;
 use rand::Rng;
-use super::ChessPlayer;
+use super::{ChessPlayer, PositionHistory, Seed};
 
 fn pick_random_move<R: Rng>(board: &Board, rng: &mut R) -> ChessMove {
     let movegen = MoveGen::new_legal(&board);
@@ -11,19 +11,24 @@ fn pick_random_move<R: Rng>(board: &Board, rng: &mut R) -> ChessMove {
     }
 }
 
-pub struct RandomPlayer<R: Rng> {
-    rng: R
+#[derive(Clone)]
+pub struct RandomPlayer {
+    seed: Seed
 }
 
 #[allow(dead_code)]
-pub fn random_player() -> RandomPlayer<rand::rngs::ThreadRng> {
+pub fn random_player(seed: Seed) -> RandomPlayer {
     RandomPlayer {
-        rng: rand::thread_rng()
+        seed
     }
 }
 
-impl<R: Rng> ChessPlayer for RandomPlayer<R> {
-    fn pick_move(&mut self, board: &Board, _logger: &mut super::Logger) -> ChessMove {
-        pick_random_move(board, &mut self.rng)
+impl ChessPlayer for RandomPlayer {
+    fn pick_move(&mut self, board: &Board, _history: &PositionHistory, _logger: &mut super::Logger) -> ChessMove {
+        pick_random_move(board, &mut super::decision_rng(self.seed, board))
+    }
+
+    fn set_seed(&mut self, seed: Seed) {
+        self.seed = seed;
     }
 }
@@ -0,0 +1,435 @@
+use super::ChessPlayer;
+use super::{astar, evaldriven, exhaustive, external, human, montecarlo, random};
+use super::astar::astarparl;
+use super::{MoveCount, Seed};
+use super::timeman::Allotment;
+use crate::utils::display;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/* Build players from specification strings, e.g.
+ *
+ *   astar:time=100ms
+ *   astarparl:time=100ms,threads=4
+ *   astar:time=1s,ponder=true
+ *   astar:descents=2000,seed=42
+ *   mc:rollout=eval,depth=40
+ *   mc:runs=500,seed=42
+ *   exhaustive:depth=3,seed=42
+ *   uci:cmd=/usr/bin/stockfish,time=50ms
+ *
+ * The player name comes first, optionally followed by a colon and a
+ * comma-separated list of parameters. Parameters that are not given
+ * take their default value. Players without a seed get a random one.
+ *
+ * A game can be replayed move for move when its players are given the
+ * same seeds, as long as their search doesn't depend on time: that holds
+ * for the exhaustive, eval and random players, and for astar and mc
+ * when they are given a number of descents or runs instead of a time.
+ * The threads of astarparl and the pondering are never reproducible.
+ */
+
+pub type SpecErr = String;
+
+/********** Registry **********/
+
+type BuildFn = fn(&Params) -> Result<Box<dyn ChessPlayer>, SpecErr>;
+
+struct Entry {
+    name:   &'static str,
+    params: &'static [&'static str],
+    build:  BuildFn,
+}
+
+const REGISTRY: &[Entry] = &[
+    Entry { name: "astar",      params: &["time", "descents", "ponder", "seed"],       build: build_astar },
+    Entry { name: "astarparl",  params: &["time", "threads", "ponder", "seed"],        build: build_astarparl },
+    Entry { name: "mc",         params: &["time", "runs", "rollout", "depth", "seed"], build: build_monte_carlo },
+    Entry { name: "exhaustive", params: &["depth", "seed"],                            build: build_exhaustive },
+    Entry { name: "eval",       params: &["seed"],                                     build: build_eval },
+    Entry { name: "random",     params: &["seed"],                                     build: build_random },
+    Entry { name: "human",      params: &[],                                           build: build_human },
+    Entry { name: "uci",        params: &["cmd", "time"],                              build: build_external },
+];
+
+const DEFAULT_ASTAR_TIME:       Duration               = Duration::from_millis(100);
+const DEFAULT_THREADS:          astarparl::ThreadCount = 4;
+const DEFAULT_EXHAUSTIVE_DEPTH: MoveCount              = 2;
+
+fn build_astar(params: &Params) -> Result<Box<dyn ChessPlayer>, SpecErr> {
+    let time = params.allotment("descents", DEFAULT_ASTAR_TIME)?;
+    let pondering = params.get("ponder", parse_bool, false)?;
+    if pondering && params.is_given("descents") {
+        return Err(String::from("Parameter \"ponder\" can't be used with \"descents\": the pondering depends on time"));
+    }
+    Ok(Box::new(astar::astar_player(time, pondering, params.seed()?)))
+}
+
+fn build_astarparl(params: &Params) -> Result<Box<dyn ChessPlayer>, SpecErr> {
+    let time_budget = params.get("time", parse_duration, DEFAULT_ASTAR_TIME)?;
+    let n_threads = params.get("threads", parse_number, DEFAULT_THREADS)?;
+    let pondering = params.get("ponder", parse_bool, false)?;
+    Ok(Box::new(astarparl::parallel_player(time_budget, n_threads, pondering, params.seed()?)))
+}
+
+fn build_monte_carlo(params: &Params) -> Result<Box<dyn ChessPlayer>, SpecErr> {
+    let time = params.allotment("runs", montecarlo::DEFAULT_TIME_BUDGET)?;
+    let rollout_depth = params.get("depth", parse_number, montecarlo::DEFAULT_ROLLOUT_DEPTH)?;
+    let rollout = params.get("rollout", parse_rollout, Rollout::Eval)?;
+    let seed = params.seed()?;
+
+    /* The rollout players are reseeded by the Monte Carlo player */
+    let player: Box<dyn ChessPlayer> = match rollout {
+        Rollout::Eval =>
+            Box::new(montecarlo::monte_carlo1(evaldriven::classic_eval_player(seed),
+                                              time,
+                                              rollout_depth,
+                                              seed)),
+        Rollout::Random =>
+            Box::new(montecarlo::monte_carlo1(random::random_player(seed),
+                                              time,
+                                              rollout_depth,
+                                              seed)),
+    };
+    Ok(player)
+}
+
+fn build_exhaustive(params: &Params) -> Result<Box<dyn ChessPlayer>, SpecErr> {
+    let depth = params.get("depth", parse_number, DEFAULT_EXHAUSTIVE_DEPTH)?;
+    if depth == 0 {
+        return Err(String::from("Parameter \"depth\" must be at least 1"));
+    }
+    Ok(Box::new(exhaustive::exhaustive_search_player(depth, params.seed()?)))
+}
+
+fn build_eval(params: &Params) -> Result<Box<dyn ChessPlayer>, SpecErr> {
+    Ok(Box::new(evaldriven::classic_eval_player(params.seed()?)))
+}
+
+fn build_random(params: &Params) -> Result<Box<dyn ChessPlayer>, SpecErr> {
+    Ok(Box::new(random::random_player(params.seed()?)))
+}
+
+fn build_human(_params: &Params) -> Result<Box<dyn ChessPlayer>, SpecErr> {
+    Ok(Box::new(human::terminal_player()))
+}
+
+/* The command may contain spaces to give arguments to the engine */
+fn build_external(params: &Params) -> Result<Box<dyn ChessPlayer>, SpecErr> {
+    let command = params.get("cmd", |cmd| Ok(Some(String::from(cmd))), None)?
+                        .ok_or("Player \"uci\" requires the engine command (cmd=<path>)")?;
+    let time_budget = params.get("time", parse_duration, DEFAULT_ASTAR_TIME)?;
+    Ok(Box::new(external::ExternalEnginePlayer::start(&command, time_budget)?))
+}
+
+/* Short description of the known players and their parameters */
+pub fn describe_players() -> String {
+    let lines = REGISTRY.iter().map(|entry|
+        if entry.params.is_empty() {
+            format!("  {}", entry.name)
+        }
+        else {
+            let params = entry.params.iter().map(|p| format!("{}=...", p));
+            format!("  {}:{}", entry.name, display::join(params, ","))
+        });
+    display::join(lines, "\n")
+}
+
+/********** PlayerSpec **********/
+
+#[derive(Clone)]
+struct Param {
+    key:        String,
+    value:      String,
+    is_default: bool,  /* not given by the user */
+}
+
+#[derive(Clone)]
+pub struct PlayerSpec {
+    name:   String,
+    params: Vec<Param>,
+}
+
+impl PlayerSpec {
+    /* Value to use if the spec doesn't give one and the player accepts this parameter */
+    pub fn with_default(mut self, key: &str, value: String) -> Self {
+        if self.params.iter().all(|p| p.key != key) {
+            self.params.push(Param {
+                key: String::from(key),
+                value,
+                is_default: true,
+            });
+        }
+        self
+    }
+
+    /* Seed used by the player, when it was given or set by default */
+    pub fn with_seed(self, seed: Seed) -> Self {
+        self.with_default("seed", seed.to_string())
+    }
+
+    /* None if the player doesn't take this parameter, or wasn't given one */
+    pub fn param(&self, key: &str) -> Option<&str> {
+        let is_taken = REGISTRY.iter()
+                               .find(|entry| entry.name == self.name)
+                               .is_some_and(|entry| entry.params.contains(&key));
+        if !is_taken {
+            return None;
+        }
+        self.params.iter()
+                   .find(|p| p.key == key)
+                   .map(|p| p.value.as_str())
+    }
+
+    /* None if the player takes no seed, or wasn't given one */
+    pub fn seed(&self) -> Option<Seed> {
+        self.param("seed")
+            .and_then(|seed| seed.parse::<Seed>().ok())
+    }
+
+    /* Whether the player plays the same moves again with the same seed */
+    pub fn is_reproducible(&self) -> bool {
+        match self.name.as_str() {
+            "exhaustive" | "eval" | "random" => true,
+            "astar"                          => self.param("descents").is_some(),
+            "mc"                             => self.param("runs").is_some(),
+            _                                => false,
+        }
+    }
+
+    /* A person or another program plays the moves */
+    pub fn is_external(&self) -> bool {
+        matches!(self.name.as_str(), "human" | "uci")
+    }
+
+    pub fn build(&self) -> Result<Box<dyn ChessPlayer>, SpecErr> {
+        let entry = REGISTRY.iter()
+                            .find(|entry| entry.name == self.name)
+                            .ok_or(format!("Unknown player \"{}\" (expected one of: {})",
+                                           self.name,
+                                           display::join(REGISTRY.iter().map(|e| String::from(e.name)), ", ")))?;
+
+        for param in self.params.iter().filter(|p| !p.is_default) {
+            if !entry.params.contains(&param.key.as_str()) {
+                return Err(unknown_param(&param.key, entry));
+            }
+        }
+
+        let params = Params { spec: self };
+        (entry.build)(&params)
+    }
+}
+
+fn unknown_param(key: &str, entry: &Entry) -> SpecErr {
+    if entry.params.is_empty() {
+        format!("Unknown parameter \"{}\" for player \"{}\" (it takes no parameter)", key, entry.name)
+    }
+    else {
+        format!("Unknown parameter \"{}\" for player \"{}\" (expected one of: {})",
+                key, entry.name, entry.params.join(", "))
+    }
+}
+
+impl FromStr for PlayerSpec {
+    type Err = SpecErr;
+
+    fn from_str(s: &str) -> Result<Self, SpecErr> {
+        let mut parts = s.trim().splitn(2, ':');
+        let name = parts.next().unwrap();
+        if name.is_empty() {
+            return Err(format!("Missing player name in \"{}\"", s));
+        }
+
+        let mut params: Vec<Param> = Vec::new();
+        let param_list = parts.next().unwrap_or("");
+        for param_str in param_list.split(',').filter(|p| !p.is_empty()) {
+            let mut key_value = param_str.splitn(2, '=');
+            let key = key_value.next().unwrap().trim();
+            let value = key_value.next()
+                                 .ok_or(format!("Parameter \"{}\" has no value (expected {}=<value>)", key, key))?
+                                 .trim();
+
+            if params.iter().any(|p| p.key == key) {
+                return Err(format!("Parameter \"{}\" is given twice", key));
+            }
+            params.push(Param {
+                key:        String::from(key),
+                value:      String::from(value),
+                is_default: false,
+            });
+        }
+
+        Ok(PlayerSpec {
+            name: String::from(name),
+            params
+        })
+    }
+}
+
+/* The canonical form only contains the parameters given by the user */
+impl fmt::Display for PlayerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let user_params: Vec<_> = self.params.iter()
+                                             .filter(|p| !p.is_default)
+                                             .map(|p| format!("{}={}", p.key, p.value))
+                                             .collect();
+        if user_params.is_empty() {
+            write!(f, "{}", self.name)
+        }
+        else {
+            write!(f, "{}:{}", self.name, user_params.join(","))
+        }
+    }
+}
+
+/********** Parameter parsing **********/
+
+struct Params<'a> {
+    spec: &'a PlayerSpec,
+}
+
+impl<'a> Params<'a> {
+    fn get<T, F>(&self, key: &str, parse: F, default: T) -> Result<T, SpecErr>
+        where F: Fn(&str) -> Result<T, SpecErr>
+    {
+        match self.spec.params.iter().find(|p| p.key == key) {
+            Some(param) =>
+                parse(&param.value)
+                    .map_err(|reason| format!("Invalid value for parameter \"{}\" of player \"{}\": {}",
+                                              key, self.spec.name, reason)),
+            None => Ok(default),
+        }
+    }
+
+    /* Given by the user, not by default */
+    fn is_given(&self, key: &str) -> bool {
+        self.spec.params.iter().any(|p| p.key == key && !p.is_default)
+    }
+
+    /* The time budget, unless a number of iterations is given instead */
+    fn allotment(&self, iterations_key: &str, default_time: Duration) -> Result<Allotment, SpecErr> {
+        if self.is_given(iterations_key) && self.is_given("time") {
+            return Err(format!("Parameters \"time\" and \"{}\" can't be given together", iterations_key));
+        }
+        match self.get(iterations_key, |s| parse_number(s).map(Some), None)? {
+            Some(0)            => Err(format!("Parameter \"{}\" must be at least 1", iterations_key)),
+            Some(n_iterations) => Ok(Allotment::Iterations(n_iterations)),
+            None               => Ok(Allotment::PerMove(self.get("time", parse_duration, default_time)?)),
+        }
+    }
+
+    fn seed(&self) -> Result<Seed, SpecErr> {
+        self.get("seed", parse_number, super::random_seed())
+    }
+}
+
+fn parse_number<T: FromStr>(s: &str) -> Result<T, SpecErr> {
+    s.parse::<T>()
+     .map_err(|_| format!("\"{}\" is not a valid number", s))
+}
+
+fn parse_bool(s: &str) -> Result<bool, SpecErr> {
+    match s {
+        "true"  => Ok(true),
+        "false" => Ok(false),
+        _       => Err(format!("\"{}\" is not a valid boolean (expected true or false)", s)),
+    }
+}
+
+/* "250ms", "2s", "1.5s" or a plain number of milliseconds */
+pub fn parse_duration(s: &str) -> Result<Duration, SpecErr> {
+    let invalid = || format!("\"{}\" is not a valid duration (e.g. 100ms or 2s)", s);
+
+    if let Some(ms) = s.strip_suffix("ms") {
+        ms.parse::<u64>()
+          .map(Duration::from_millis)
+          .map_err(|_| invalid())
+    }
+    else if let Some(secs) = s.strip_suffix('s') {
+        /* Negative, NaN, infinite or too large durations are rejected */
+        secs.parse::<f64>()
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            .ok_or_else(invalid)
+    }
+    else {
+        s.parse::<u64>()
+         .map(Duration::from_millis)
+         .map_err(|_| invalid())
+    }
+}
+
+enum Rollout {
+    Eval,
+    Random,
+}
+
+fn parse_rollout(s: &str) -> Result<Rollout, SpecErr> {
+    match s {
+        "eval"   => Ok(Rollout::Eval),
+        "random" => Ok(Rollout::Random),
+        _        => Err(format!("unknown rollout player \"{}\" (expected eval or random)", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging;
+    use crate::play::{Logger, PositionHistory};
+    use chess::{Board, ChessMove};
+    use std::io;
+
+    /* The moves of a player that plays both sides from the initial position */
+    fn self_play(spec: &str, n_plies: usize) -> Vec<ChessMove> {
+        self_play_logged(spec, n_plies, &mut logging::ignore_all())
+    }
+
+    fn self_play_logged(spec: &str, n_plies: usize, logger: &mut Logger) -> Vec<ChessMove> {
+        let mut player = spec.parse::<PlayerSpec>().unwrap().build().unwrap();
+        let mut board = Board::default();
+        let mut history = PositionHistory::new(&board);
+        let mut moves = Vec::new();
+        for _ in 0..n_plies {
+            let mv = player.pick_move(&board, &history, logger);
+            history.push(&board, mv);
+            board = board.make_move_new(mv);
+            moves.push(mv);
+        }
+        moves
+    }
+
+    #[test]
+    fn a_number_of_descents_or_runs_replays_the_same_moves() {
+        for spec in ["astar:descents=200,seed=7", "mc:runs=50,depth=10,seed=7"] {
+            assert_eq!(self_play(spec, 6), self_play(spec, 6), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn the_moves_do_not_depend_on_the_log_level() {
+        let spec = "astar:descents=200,seed=7";
+        let mut logger = logging::log_to(io::sink(), logging::LogLevel::Trace);
+        assert_eq!(self_play(spec, 8), self_play_logged(spec, 8, &mut logger));
+    }
+
+    #[test]
+    fn a_number_of_descents_is_kept_whatever_the_time() {
+        let spec = "astar:descents=200,seed=7";
+        let mut player = spec.parse::<PlayerSpec>().unwrap().build().unwrap();
+        player.set_time_budget(Duration::from_millis(1));
+
+        let board = Board::default();
+        let mv = player.pick_move(&board, &PositionHistory::new(&board), &mut logging::ignore_all());
+        assert_eq!(mv, self_play(spec, 1)[0]);
+    }
+
+    #[test]
+    fn descents_exclude_a_time_and_pondering() {
+        for spec in ["astar:descents=200,time=1s", "astar:descents=200,ponder=true", "astar:descents=0", "mc:runs=50,time=1s"] {
+            assert!(spec.parse::<PlayerSpec>().unwrap().build().is_err(), "{}", spec);
+        }
+        assert!("astarparl:descents=200".parse::<PlayerSpec>().unwrap().build().is_err());
+    }
+}
@@ -54,20 +54,6 @@ pub fn depth<N: SearchNode>(tree: N) -> u16 {
     return rec_depth + 1;
 }
 
-pub fn bifold<N, E, V, FN, FE>(tree: N, init_value: V, process_node: FN, process_branch: FE) -> V
-    where
-        N: SearchNode<Branch=E>,
-        E: SearchBranch<Node=N>,
-        FN: FnMut(V, &N) -> V,
-        FE: FnMut(V, &N, &E) -> V
-{
-    let mut result = process_node(init_value, &tree);
-    for branch in tree.branches() {
-        result = process_branch(result, &tree, &branch);
-        result = bifold(tree.clone())
-    }
-}
-
 enum TreeItem<N, E> {
     Node(N),
     Branch(N, E),
@@ -99,12 +85,14 @@ trait TreeIterator: Iterator<Item=TreeItem<Self::Node, Self::Branch>> {
 
 pub type Tree<N, M> = Node<N, M>;
 
+#[derive(Clone)]
 pub struct Node<N, M> {
     pub board:      Board,
     pub node_data:  N,
     pub moves:      Vec<Branch<N, M>>
 }
 
+#[derive(Clone)]
 pub struct Branch<N, M> {
     pub mv:         ChessMove,
     pub mv_data:    M,
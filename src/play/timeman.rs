@@ -0,0 +1,142 @@
+use chess::{Board, ChessMove, Piece};
+use super::clock::ClockInfo;
+use std::cmp;
+use std::time::{Duration, Instant};
+
+/* Time management for the players that search until their time is up.
+ *
+ * The time of the whole game is turned into a budget for the move, which
+ * depends on the time left and on the game phase. While searching, the
+ * player tells its current best move: when it changes late, the budget
+ * is extended (up to a maximum), and when one move clearly dominates the
+ * others, the search stops early.
+ *
+ * A search may rather be given a fixed number of iterations, so that it
+ * doesn't depend on the speed of the machine: with the same seed, it then
+ * plays the same moves again.
+ */
+
+/* The time given to a player */
+#[derive(Clone, Copy)]
+pub enum Allotment {
+    PerMove(Duration),  /* a fixed budget for every move, never exceeded */
+    Clock(ClockInfo),   /* the time left for the rest of the game */
+    Iterations(u32),    /* a fixed number of iterations, whatever the time they take */
+}
+
+/* Kept aside on the clock, for the exchanges with the arbiter */
+const MOVE_OVERHEAD: Duration = Duration::from_millis(20);
+
+/* Expected number of moves left, from a full board to bare kings */
+const MAX_MOVES_TO_GO: u32 = 40;
+const MIN_MOVES_TO_GO: u32 = 15;
+
+/* A budget is extended to at most that many times its base */
+const MAX_EXTENSION: u32 = 3;
+/* A best move found after this fraction of the base budget is a late change */
+const LATE_CHANGE_DIVISOR: u32 = 2;
+/* A dominant move is played once this fraction of the base budget is spent */
+const DOMINANCE_DIVISOR: u32 = 4;
+
+impl Allotment {
+    /* The time given by the game or the arbiter doesn't change a fixed
+     * number of iterations
+     */
+    pub fn replaced_by(self, time: Allotment) -> Allotment {
+        match self {
+            Allotment::Iterations(_) => self,
+            _                        => time,
+        }
+    }
+
+    /* The budget of a normal move, and the most that may be spent on it */
+    fn budgets(&self, board: &Board) -> (Duration, Duration) {
+        match self {
+            Allotment::PerMove(budget) => (*budget, *budget),
+            Allotment::Clock(clock) => {
+                let time_left = clock.time_left.saturating_sub(MOVE_OVERHEAD);
+                let moves_to_go = cmp::max(clock.moves_to_go.unwrap_or_else(|| expected_moves_to_go(board)), 1);
+
+                /* never bet more than half of the remaining time on a single move */
+                let base = cmp::min(time_left / moves_to_go + clock.increment, time_left / 2);
+                let max = cmp::min(base * MAX_EXTENSION, time_left / 2);
+                (base, max)
+            }
+            Allotment::Iterations(_) => (Duration::MAX, Duration::MAX),
+        }
+    }
+}
+
+/* Fewer moves are left as the material comes off the board */
+fn expected_moves_to_go(board: &Board) -> u32 {
+    const INITIAL_MATERIAL: u32 = 78;
+    const VALUES: [(Piece, u32); 5] = [(Piece::Pawn, 1), (Piece::Knight, 3), (Piece::Bishop, 3), (Piece::Rook, 5), (Piece::Queen, 9)];
+
+    let material: u32 = VALUES.iter()
+                              .map(|(piece, value)| board.pieces(*piece).popcnt() * value)
+                              .sum();
+    let material = cmp::min(material, INITIAL_MATERIAL);
+
+    MIN_MOVES_TO_GO + (MAX_MOVES_TO_GO - MIN_MOVES_TO_GO) * material / INITIAL_MATERIAL
+}
+
+pub struct TimeManager {
+    start:           Instant,
+    base:            Duration,
+    max:             Duration,
+    budget:          Duration,     /* the base, extended after late changes of the best move */
+    best:            Option<ChessMove>,
+    stopped:         bool,
+    iterations_left: Option<u32>,  /* for a fixed number of iterations, the time doesn't count */
+}
+
+impl TimeManager {
+    pub fn start(allotment: &Allotment, board: &Board) -> Self {
+        let (base, max) = allotment.budgets(board);
+        let iterations_left = match allotment {
+            Allotment::Iterations(n_iterations) => Some(*n_iterations),
+            _                                   => None,
+        };
+        TimeManager {
+            start:   Instant::now(),
+            base,
+            max,
+            budget:  base,
+            best:    None,
+            stopped: false,
+            iterations_left,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /* Tells whether the search goes on, given its current best move and
+     * whether this move clearly dominates the others. Once the answer is
+     * no, it stays no.
+     */
+    pub fn keep_searching(&mut self, best: ChessMove, dominant: bool) -> bool {
+        if self.stopped {
+            return false;
+        }
+        if let Some(iterations_left) = self.iterations_left.as_mut() {
+            self.stopped = *iterations_left == 0;
+            *iterations_left = iterations_left.saturating_sub(1);
+            return !self.stopped;
+        }
+
+        let elapsed = self.elapsed();
+        if self.best != Some(best) {
+            if self.best.is_some() && elapsed >= self.base / LATE_CHANGE_DIVISOR {
+                /* give the search some time to confirm the new best move */
+                self.budget = cmp::min(self.max, cmp::max(self.budget, elapsed + self.base / 2));
+            }
+            self.best = Some(best);
+        }
+
+        let dominates = dominant && elapsed >= self.base / DOMINANCE_DIVISOR;
+        self.stopped = dominates || elapsed >= self.budget;
+        !self.stopped
+    }
+}
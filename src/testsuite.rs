@@ -0,0 +1,173 @@
+use chess::{Board, ChessMove};
+use crate::epd::{EpdErr, EpdRecord};
+use crate::pgn;
+use crate::play::{self, ChessPlayer, Decision, PositionHistory, SearchInfo};
+use crate::play::registry::PlayerSpec;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/* Tactical test suites: EPD positions with a best move ("bm") to find,
+ * or a move to avoid ("am").
+ *
+ * Every position is searched once, under the same time budget. The time
+ * to solution is the time that search took, when it found a solution.
+ */
+
+pub struct TestPosition {
+    pub id:          String,
+    pub board:       Board,
+    pub best_moves:  Vec<ChessMove>,
+    pub avoid_moves: Vec<ChessMove>,
+    pub expected:    String,  /* e.g. "bm Qxf7#", as given in the suite */
+}
+
+impl TestPosition {
+    pub fn is_solution(&self, mv: ChessMove) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&mv))
+            && !self.avoid_moves.contains(&mv)
+    }
+}
+
+pub fn load_suite(path: &Path) -> Result<Vec<TestPosition>, EpdErr> {
+    let content = std::fs::read_to_string(path)
+                     .map_err(|reason| format!("Couldn't read {}: {}", path.display(), reason))?;
+    read_suite(&content)
+        .map_err(|reason| format!("{}: {}", path.display(), reason))
+}
+
+/* Empty lines and lines starting with '#' are ignored */
+pub fn read_suite(input: &str) -> Result<Vec<TestPosition>, EpdErr> {
+    let mut positions = Vec::new();
+
+    for (line_idx, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let position = read_position(line, positions.len())
+                           .map_err(|reason| format!("line {}: {}", line_idx + 1, reason))?;
+        positions.push(position);
+    }
+
+    Ok(positions)
+}
+
+fn read_position(line: &str, position_idx: usize) -> Result<TestPosition, EpdErr> {
+    let record = line.parse::<EpdRecord>()?;
+    let parse_moves = |opcode: &str| -> Result<Vec<ChessMove>, EpdErr> {
+        record.operands(opcode)
+              .unwrap_or(&[])
+              .iter()
+              .map(|mv_str| pgn::parse_move(&record.board, mv_str).map_err(|err| err.to_string()))
+              .collect()
+    };
+
+    let best_moves = parse_moves("bm")?;
+    let avoid_moves = parse_moves("am")?;
+    if best_moves.is_empty() && avoid_moves.is_empty() {
+        return Err(String::from("The position has neither a 'bm' nor an 'am' operation"));
+    }
+
+    let expected = ["bm", "am"].iter()
+                               .filter_map(|opcode| record.operands(opcode)
+                                                          .map(|moves| format!("{} {}", opcode, moves.join(" "))))
+                               .collect::<Vec<_>>()
+                               .join(", ");
+    let id = record.operands("id")
+                   .and_then(|operands| operands.first().cloned())
+                   .unwrap_or(format!("#{}", position_idx + 1));
+
+    Ok(TestPosition {
+        id,
+        board: record.board,
+        best_moves,
+        avoid_moves,
+        expected,
+    })
+}
+
+/********** Running **********/
+
+pub struct TestResult {
+    pub played:           Result<ChessMove, String>,  /* why the player played no move otherwise */
+    pub info:             Option<SearchInfo>,
+    pub time_to_solution: Option<Duration>,           /* None if unsolved */
+}
+
+/* A player that can't be built or that gives up (e.g. an external engine
+ * that died) fails the position, the suite goes on with the next one.
+ */
+pub fn run_position(
+    spec:        &PlayerSpec,
+    position:    &TestPosition,
+    time_budget: Duration,
+    logger:      &mut play::Logger)
+    -> TestResult
+{
+    let failed = |reason| TestResult { played: Err(reason), info: None, time_to_solution: None };
+
+    let mut player = match spec.build() {
+        Ok(player)  => player,
+        Err(reason) => return failed(reason),
+    };
+    player.set_time_budget(time_budget);
+
+    let start = Instant::now();
+    let (decision, info) = player.decide_with_info(&position.board, &PositionHistory::new(&position.board), logger);
+    let duration = start.elapsed();
+
+    match decision {
+        Decision::Play(played) => TestResult {
+            played: Ok(played),
+            info,
+            time_to_solution: if position.is_solution(played) { Some(duration) } else { None },
+        },
+        _ => failed(format!("{} gave up", spec)),
+    }
+}
+
+pub struct SuiteSummary {
+    pub n_positions: usize,
+    pub n_solved:    usize,
+    pub total_time:  Duration,  /* to solution, of the solved positions */
+}
+
+impl SuiteSummary {
+    pub fn average_time(&self) -> Option<Duration> {
+        if self.n_solved == 0 {
+            None
+        }
+        else {
+            Some(self.total_time / self.n_solved as u32)
+        }
+    }
+}
+
+pub fn run_suite<F>(
+    spec:        &PlayerSpec,
+    suite:       &[TestPosition],
+    time_budget: Duration,
+    logger:      &mut play::Logger,
+    mut on_test: F)
+    -> SuiteSummary
+    where
+        F: FnMut(&TestPosition, &TestResult)
+{
+    let mut summary = SuiteSummary {
+        n_positions: suite.len(),
+        n_solved:    0,
+        total_time:  Duration::from_secs(0),
+    };
+
+    for position in suite {
+        let result = run_position(spec, position, time_budget, logger);
+        if let Some(time) = result.time_to_solution {
+            summary.n_solved += 1;
+            summary.total_time += time;
+        }
+        on_test(position, &result);
+    }
+
+    summary
+}
@@ -0,0 +1,278 @@
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen};
+use crate::play::{self, ChessPlayer, Decision, Game, SearchInfo};
+use crate::play::clock::ClockInfo;
+use crate::eval;
+use crate::utils::display;
+use std::io::{self, BufRead, Write};
+use std::str::{FromStr, SplitWhitespace};
+use std::time::Duration;
+use std::cmp;
+
+/* A UCI front-end for any ChessPlayer.
+ * The protocol is described in http://wbec-ridderkerk.nl/html/UCIProtocol.html
+ *
+ * The search is run synchronously: a 'stop' sent during a search is only
+ * read once the player is done with its time budget, which is when the
+ * best move gets reported anyway.
+ *
+ * Pondering is the exception: 'go ponder' lets the player search the
+ * position in the background, until either 'ponderhit' (the opponent played
+ * the expected reply, the search goes on as a normal one) or 'stop' (the
+ * opponent played another move, the GUI ignores the best move we report).
+ */
+
+const ENGINE_NAME:   &str = "hakarl";
+const ENGINE_AUTHOR: &str = "Hugo Kapp";
+
+pub fn run_uci<P: ChessPlayer>(player: &mut P, logger: &mut play::Logger) -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    run_uci_on(player, stdin.lock(), stdout.lock(), logger)
+}
+
+pub fn run_uci_on<P, R, W>(
+    player: &mut P,
+    input:  R,
+    output: W,
+    logger: &mut play::Logger)
+    -> io::Result<()>
+    where
+        P: ChessPlayer,
+        R: BufRead,
+        W: Write
+{
+    let mut output = output;
+    let mut game = Game::new();
+    let mut pondering: Option<GoParams> = None;  /* the 'go ponder' in progress */
+
+    for line in input.lines() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("uci") => {
+                writeln!(output, "id name {}", ENGINE_NAME)?;
+                writeln!(output, "id author {}", ENGINE_AUTHOR)?;
+                if player.can_ponder() {
+                    writeln!(output, "option name Ponder type check default false")?;
+                }
+                writeln!(output, "uciok")?;
+            }
+
+            Some("isready") => writeln!(output, "readyok")?,
+
+            Some("ucinewgame") => game = Game::new(),
+
+            Some("position") =>
+                match parse_position(tokens) {
+                    Ok(new_game) => game = new_game,
+                    Err(reason)  => writeln!(output, "info string {}", reason)?,
+                },
+
+            Some("go") => {
+                let go_params = parse_go(tokens);
+                if go_params.ponder {
+                    player.ponder(&game.final_board, &game.history);
+                    pondering = Some(go_params);
+                }
+                else {
+                    go(player, &game, &go_params, &mut output, logger)?;
+                }
+            }
+
+            Some("ponderhit") =>
+                if let Some(go_params) = pondering.take() {
+                    go(player, &game, &go_params, &mut output, logger)?;
+                },
+
+            Some("stop") =>
+                /* Only the pondering runs in the background */
+                if let Some(go_params) = pondering.take() {
+                    match player.stop_pondering() {
+                        Some(mv) => writeln!(output, "bestmove {}", mv)?,
+                        None     => go(player, &game, &go_params, &mut output, logger)?,
+                    }
+                },
+
+            Some("quit") => return Ok(()),
+
+            _ => { /* unknown commands must be ignored */ }
+        }
+
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+/********** position **********/
+
+type UciErr = String;
+
+fn parse_position(mut tokens: SplitWhitespace) -> Result<Game, UciErr> {
+    let init_board = match tokens.next() {
+        Some("startpos") => {
+            /* skip the "moves" keyword, if any */
+            tokens.next();
+            Board::default()
+        }
+
+        Some("fen") => {
+            let fen_fields: Vec<_> = tokens.by_ref()
+                                           .take_while(|tok| *tok != "moves")
+                                           .collect();
+            let fen = fen_fields.join(" ");
+            Board::from_str(&fen)
+                .map_err(|err| format!("Invalid fen \"{}\": {}", fen, err))?
+        }
+
+        Some(other) => return Err(format!("Unknown position type \"{}\"", other)),
+
+        None => return Err(String::from("Missing position")),
+    };
+
+    let mut game = Game::starting_from(init_board);
+    for mv_str in tokens {
+        let mv = parse_coord_move(&game.final_board, mv_str)
+                    .ok_or(format!("Illegal move \"{}\"", mv_str))?;
+        game.play_move(mv);
+    }
+
+    Ok(game)
+}
+
+/* Moves are given in coordinate notation, e.g. "e2e4" or "e7e8q" */
+pub fn parse_coord_move(board: &Board, mv_str: &str) -> Option<ChessMove> {
+    MoveGen::new_legal(board)
+        .find(|mv| format!("{}", mv) == mv_str)
+}
+
+/********** go **********/
+
+#[derive(Default)]
+struct GoParams {
+    ponder:    bool,
+    movetime:  Option<Duration>,
+    wtime:     Option<Duration>,
+    btime:     Option<Duration>,
+    winc:      Option<Duration>,
+    binc:      Option<Duration>,
+    movestogo: Option<u32>,
+}
+
+fn parse_go(mut tokens: SplitWhitespace) -> GoParams {
+    fn next_ms(tokens: &mut SplitWhitespace) -> Option<Duration> {
+        tokens.next()
+              .and_then(|tok| tok.parse::<i64>().ok())
+              /* some GUIs send negative times when the clock runs out */
+              .map(|ms| Duration::from_millis(cmp::max(ms, 0) as u64))
+    }
+
+    let mut params = GoParams::default();
+    while let Some(tok) = tokens.next() {
+        match tok {
+            "ponder"    => params.ponder = true,
+            "movetime"  => params.movetime = next_ms(&mut tokens),
+            "wtime"     => params.wtime = next_ms(&mut tokens),
+            "btime"     => params.btime = next_ms(&mut tokens),
+            "winc"      => params.winc = next_ms(&mut tokens),
+            "binc"      => params.binc = next_ms(&mut tokens),
+            "movestogo" => params.movestogo = tokens.next().and_then(|tok| tok.parse().ok()),
+            _           => { /* infinite, depth, nodes... keep the player's own budget */ }
+        }
+    }
+    params
+}
+
+/* Hand the time control to the player.
+ * Without any, the player keeps its own budget.
+ */
+fn set_time_control<P: ChessPlayer>(player: &mut P, params: &GoParams, color: Color) {
+    if let Some(movetime) = params.movetime {
+        return player.set_time_budget(movetime);
+    }
+
+    let (time_left, increment, opponent_time_left) = match color {
+        Color::White => (params.wtime, params.winc, params.btime),
+        Color::Black => (params.btime, params.binc, params.wtime),
+    };
+
+    if let Some(time_left) = time_left {
+        player.set_clock(&ClockInfo {
+            time_left,
+            opponent_time_left: opponent_time_left.unwrap_or(time_left),
+            increment:          increment.unwrap_or_default(),
+            moves_to_go:        params.movestogo,
+        });
+    }
+}
+
+fn go<P: ChessPlayer, W: Write>(
+    player:    &mut P,
+    game:      &Game,
+    params:    &GoParams,
+    output:    &mut W,
+    logger:    &mut play::Logger)
+    -> io::Result<()>
+{
+    let board = &game.final_board;
+    if board.status() != BoardStatus::Ongoing {
+        /* No legal move: report the null move */
+        return writeln!(output, "bestmove 0000");
+    }
+
+    set_time_control(player, params, board.side_to_move());
+    let (decision, search_info) = player.decide_with_info(board, &game.history, logger);
+    let best_move = match decision {
+        Decision::Play(mv) => mv,
+        /* The player gives up, e.g. its engine died: nothing is played */
+        _ => return writeln!(output, "bestmove 0000"),
+    };
+
+    /* The second move of the line is the reply we would ponder on */
+    let ponder_move = search_info.as_ref().and_then(|info| info.best_line.get(1).copied());
+    if let Some(info) = search_info {
+        writeln!(output, "{}", format_info(&info))?;
+    }
+    match ponder_move {
+        Some(reply) => writeln!(output, "bestmove {} ponder {}", best_move, reply),
+        None        => writeln!(output, "bestmove {}", best_move),
+    }
+}
+
+/********** info **********/
+
+/* Our evaluation functions count in pawns */
+pub const CENTIPAWNS_PER_POINT: i32 = 100;
+
+fn format_score(score: eval::Score, line_len: usize) -> String {
+    /* The best line ends in the mate for winning and losing scores */
+    let mate_in = line_len.div_ceil(2);
+    if score == eval::Score::MAX {
+        format!("mate {}", mate_in)
+    }
+    else if score == eval::Score::MIN {
+        format!("mate -{}", mate_in)
+    }
+    else {
+        format!("cp {}", (score as i32) * CENTIPAWNS_PER_POINT)
+    }
+}
+
+fn format_info(info: &SearchInfo) -> String {
+    let ms = info.duration.as_millis();
+    let nps = (info.nodes as u128 * 1000).checked_div(ms).unwrap_or(0);
+
+    let mut res = format!("info depth {} nodes {} time {} nps {}", info.depth, info.nodes, ms, nps);
+
+    if let Some(score) = info.score {
+        res.push_str(&format!(" score {}", format_score(score, info.best_line.len())));
+    }
+
+    if !info.best_line.is_empty() {
+        let pv = display::join(info.best_line.iter().map(|mv| format!("{}", mv)), " ");
+        res.push_str(&format!(" pv {}", pv));
+    }
+
+    res
+}
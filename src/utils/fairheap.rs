@@ -14,8 +14,9 @@ pub struct FairHeap<T> {
 }
 
 impl<T: Ord> FairHeap<T> {
-    pub fn new() -> FairHeap<T> {
-        let rng = SomeRng::from_entropy();
+    /* Ties are broken at random, but the same way for the same seed */
+    pub fn new(seed: u64) -> FairHeap<T> {
+        let rng = SomeRng::seed_from_u64(seed);
 
         FairHeap {
             eq_best:    Vec::new(),
@@ -58,6 +59,19 @@ impl<T: Ord> FairHeap<T> {
         }
     }
 
+    /* The index the next pop will draw, without drawing it: looking at the
+     * heap, e.g. to log the best lines, doesn't change the next pops
+     */
+    fn next_pop_index(&self) -> Option<usize> {
+        if self.eq_best.is_empty() {
+            None
+        }
+        else {
+            let mut rng = self.rng.borrow().clone();
+            Some(rng.gen_range(0, self.eq_best.len()))
+        }
+    }
+
     pub fn pop(&mut self) -> Option<T> {
         if self.eq_best.is_empty() {
             return None;
@@ -103,7 +117,7 @@ impl<T: Ord> FairHeap<T> {
     }
 
     pub fn peek(&self) -> Option<&T> {
-        self.fair_pop_index()
+        self.next_pop_index()
             .map(|fair_idx| &self.eq_best[fair_idx])
     }
 
@@ -111,9 +125,3 @@ impl<T: Ord> FairHeap<T> {
         self.eq_best.is_empty()
     }
 }
-
-impl<T: Ord> Default for FairHeap<T> {
-    fn default() -> Self {
-        Self::new()
-    }
-}
@@ -0,0 +1,88 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/* The external engine player, run against the scripted engine of
+ * src/bin/mock_uci_engine.rs: an engine that crashes, hangs or plays an
+ * illegal move must forfeit, not take hakarl down.
+ */
+
+const HAKARL: &str = env!("CARGO_BIN_EXE_hakarl");
+const MOCK_ENGINE: &str = env!("CARGO_BIN_EXE_mock_uci_engine");
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("hakarl-test-{}-{}", std::process::id(), name))
+}
+
+fn engine_spec(mock_args: &str) -> String {
+    format!("uci:cmd={} {},time=50ms", MOCK_ENGINE, mock_args)
+}
+
+/* Plays the mock engine as white against the random player, returns the output and the pgn */
+fn play_against_mock(name: &str, mock_args: &str) -> (Output, String) {
+    let pgn_path = temp_path(&format!("{}.pgn", name));
+    let output = Command::new(HAKARL)
+                         .args(["play", "--white", &engine_spec(mock_args), "--black", "random", "--seed", "1"])
+                         .arg("--pgn").arg(&pgn_path)
+                         .arg("--log").arg(temp_path(&format!("{}.log", name)))
+                         .output()
+                         .expect("Couldn't run hakarl");
+    let pgn = std::fs::read_to_string(&pgn_path).unwrap_or_default();
+    (output, pgn)
+}
+
+fn assert_white_resigned(output: &Output, pgn: &str) {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "hakarl failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("Player Black wins by resignation"), "unexpected output: {}", stdout);
+    assert!(pgn.contains("[Result \"0-1\"]"), "unexpected pgn: {}", pgn);
+}
+
+#[test]
+fn engine_that_crashes_resigns() {
+    let (output, pgn) = play_against_mock("crash", "--crash-after 2");
+    assert_white_resigned(&output, &pgn);
+    /* the moves played before the crash are kept */
+    assert!(pgn.contains("2. ") && !pgn.contains("3. "), "unexpected pgn: {}", pgn);
+}
+
+#[test]
+fn engine_that_plays_an_illegal_move_resigns() {
+    let (output, pgn) = play_against_mock("illegal", "--illegal");
+    assert_white_resigned(&output, &pgn);
+    assert!(!pgn.contains("1. "), "unexpected pgn: {}", pgn);
+}
+
+#[test]
+fn engine_that_hangs_resigns() {
+    let (output, pgn) = play_against_mock("hang", "--hang");
+    assert_white_resigned(&output, &pgn);
+}
+
+#[test]
+fn slow_answers_within_the_margin_are_accepted() {
+    let (output, pgn) = play_against_mock("delay", "--delay 100 --crash-after 3");
+    assert_white_resigned(&output, &pgn);
+    assert!(pgn.contains("3. ") && !pgn.contains("4. "), "unexpected pgn: {}", pgn);
+}
+
+#[test]
+fn uci_driver_plays_the_null_move_for_a_dead_engine() {
+    let mut child = Command::new(HAKARL)
+                            .args(["uci", "--player", &engine_spec("--illegal"), "--log-level", "none"])
+                            .arg("--log").arg(temp_path("uci.log"))
+                            .stdin(Stdio::piped())
+                            .stdout(Stdio::piped())
+                            .spawn()
+                            .expect("Couldn't run hakarl");
+    child.stdin
+         .take()
+         .unwrap()
+         .write_all(b"uci\nisready\nposition startpos\ngo movetime 50\nquit\n")
+         .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "hakarl failed");
+    assert!(stdout.lines().any(|line| line == "bestmove 0000"), "unexpected output: {}", stdout);
+}